@@ -0,0 +1,131 @@
+//! Animated GIF and APNG encoding of rendered frames.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use color_quant::NeuQuant;
+use image::imageops::{dither, index_colors};
+use image::{Rgba, RgbaImage};
+
+use crate::image::Image;
+
+/// NeuQuant sampling factor: 1 is the slowest and best, 30 the fastest
+const QUANTIZER_SAMPLE_FACTOR: i32 = 10;
+
+/// Upper bound on the pixels used to train the shared GIF palette
+const QUANTIZER_TRAINING_PIXELS: usize = 1 << 20;
+
+/// Settings shared by the animated encoders
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnimationOptions {
+    /// Frames per second
+    pub fps: f64,
+    /// Number of times to play the animation, 0 loops forever
+    pub plays: u16,
+    /// Floyd-Steinberg dithering when reducing GIF frames to 256 colors
+    pub dither: bool,
+}
+
+impl Default for AnimationOptions {
+    fn default() -> AnimationOptions {
+        AnimationOptions { fps: 25.0, plays: 0, dither: false }
+    }
+}
+
+/// Write frames into an animated GIF file
+pub fn write_gif<P: AsRef<Path>>(filename: P, frames: &[Image], options: &AnimationOptions) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(filename)?);
+    encode_gif(&mut writer, frames, options)?;
+    writer.flush()
+}
+
+/// Encode frames as an animated GIF sharing one 256 color palette, so colors do not flicker between frames
+pub fn encode_gif<W: Write>(writer: &mut W, frames: &[Image], options: &AnimationOptions) -> std::io::Result<()> {
+    let (width, height) = frame_size(frames)?;
+    let (width, height) = (
+        u16::try_from(width).map_err(|_| std::io::Error::other("GIF frames are at most 65535 pixels wide"))?,
+        u16::try_from(height).map_err(|_| std::io::Error::other("GIF frames are at most 65535 pixels high"))?,
+    );
+
+    let quantizer = NeuQuant::new(QUANTIZER_SAMPLE_FACTOR, 256, &training_pixels(frames));
+    let palette = quantizer.color_map_rgb();
+
+    let mut encoder = gif::Encoder::new(writer, width, height, &palette).map_err(std::io::Error::other)?;
+    let repeat = if options.plays == 0 { gif::Repeat::Infinite } else { gif::Repeat::Finite(options.plays) };
+    encoder.set_repeat(repeat).map_err(std::io::Error::other)?;
+
+    // GIF delays are in hundredths of a second
+    let delay = (100.0 / options.fps).round().clamp(1.0, u16::MAX as f64) as u16;
+
+    for img in frames {
+        let mut rgba = to_rgba(img);
+        if options.dither && width > 1 && height > 1 {
+            dither(&mut rgba, &quantizer);
+        }
+        let indices = index_colors(&rgba, &quantizer).into_raw();
+
+        let mut frame = gif::Frame::from_indexed_pixels(width, height, indices, None);
+        frame.delay = delay;
+        encoder.write_frame(&frame).map_err(std::io::Error::other)?;
+    }
+
+    Ok(())
+}
+
+/// Write frames into an animated PNG file
+pub fn write_apng<P: AsRef<Path>>(filename: P, frames: &[Image], options: &AnimationOptions) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(filename)?);
+    encode_apng(&mut writer, frames, options)?;
+    writer.flush()
+}
+
+/// Encode frames as an animated PNG, losslessly
+pub fn encode_apng<W: Write>(writer: &mut W, frames: &[Image], options: &AnimationOptions) -> std::io::Result<()> {
+    let (width, height) = frame_size(frames)?;
+
+    let mut encoder = png::Encoder::new(writer, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(frames.len() as u32, options.plays as u32).map_err(std::io::Error::other)?;
+
+    // Frame delay as a fraction of a second, in milliseconds
+    let delay_ms = (1000.0 / options.fps).round().clamp(1.0, u16::MAX as f64) as u16;
+    encoder.set_frame_delay(delay_ms, 1000).map_err(std::io::Error::other)?;
+
+    let mut png_writer = encoder.write_header().map_err(std::io::Error::other)?;
+    for img in frames {
+        let data: Vec<u8> = img.pixels.iter().flat_map(|p| [p.r, p.g, p.b]).collect();
+        png_writer.write_image_data(&data).map_err(std::io::Error::other)?;
+    }
+    png_writer.finish().map_err(std::io::Error::other)
+}
+
+/// Common size of all frames
+fn frame_size(frames: &[Image]) -> std::io::Result<(usize, usize)> {
+    let first = frames.first().ok_or_else(|| std::io::Error::other("an animation needs at least one frame"))?;
+    if frames.iter().any(|img| img.width != first.width || img.height != first.height) {
+        return Err(std::io::Error::other("all frames of an animation must have the same size"));
+    }
+    Ok((first.width, first.height))
+}
+
+/// RGBA pixels spread evenly over all frames, for training the shared palette
+fn training_pixels(frames: &[Image]) -> Vec<u8> {
+    let total: usize = frames.iter().map(|img| img.pixels.len()).sum();
+    let stride = total.div_ceil(QUANTIZER_TRAINING_PIXELS).max(1);
+
+    frames
+        .iter()
+        .flat_map(|img| img.pixels.iter())
+        .step_by(stride)
+        .flat_map(|p| [p.r, p.g, p.b, 255])
+        .collect()
+}
+
+fn to_rgba(img: &Image) -> RgbaImage {
+    RgbaImage::from_fn(img.width as u32, img.height as u32, |x, y| {
+        let p = img.pixels[y as usize * img.width + x as usize];
+        Rgba([p.r, p.g, p.b, 255])
+    })
+}
@@ -0,0 +1,123 @@
+//! Keyframed zoom animations.
+//!
+//! Keyframes are spread evenly over the frames. Between two keyframes the zoom
+//! is interpolated exponentially, so the zoom speed looks constant, and the
+//! center moves in step with the view radius so the target point drifts
+//! smoothly into place instead of racing ahead of the zoom.
+
+use std::str::FromStr;
+
+use crate::fixed::{DecimalComplex, Fixed};
+use crate::viewport::Viewport;
+
+/// A view at a point in time of the animation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keyframe {
+    pub center: DecimalComplex,
+    pub zoom: f64,
+}
+
+impl FromStr for Keyframe {
+    type Err = String;
+
+    /// Parse a keyframe written as "re,im@zoom"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (center, zoom) = s
+            .rsplit_once('@')
+            .ok_or_else(|| format!("expected \"re,im@zoom\", got \"{s}\""))?;
+        let zoom = zoom.trim().parse::<f64>().map_err(|e| format!("invalid zoom: {e}"))?;
+        if !(zoom > 0.0 && zoom.is_finite()) {
+            return Err(format!("zoom must be positive, got {zoom}"));
+        }
+        Ok(Keyframe { center: center.parse()?, zoom })
+    }
+}
+
+/// Easing curve applied between consecutive keyframes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Easing {
+    #[default]
+    Linear,
+    /// Start slowly
+    EaseIn,
+    /// End slowly
+    EaseOut,
+    /// Start and end slowly
+    EaseInOut,
+}
+
+impl Easing {
+    /// Map linear progress in [0, 1] to eased progress in [0, 1]
+    pub fn apply(self, t: f64) -> f64 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+/// A sequence of keyframes rendered over a number of frames
+#[derive(Debug, Clone, PartialEq)]
+pub struct Animation {
+    pub keyframes: Vec<Keyframe>,
+    pub frames: usize,
+    pub easing: Easing,
+}
+
+impl Animation {
+    pub fn new(keyframes: Vec<Keyframe>, frames: usize, easing: Easing) -> Result<Animation, String> {
+        if keyframes.len() < 2 {
+            return Err("an animation needs at least two keyframes".to_string());
+        }
+        if frames < 2 {
+            return Err("an animation needs at least two frames".to_string());
+        }
+        Ok(Animation { keyframes, frames, easing })
+    }
+
+    /// View of frame `index`, counted from 0
+    pub fn frame(&self, index: usize) -> Keyframe {
+        // Position along the keyframes, e.g. 1.25 is a quarter of the way from the second to the third
+        let segments = self.keyframes.len() - 1;
+        let position = index as f64 / (self.frames - 1) as f64 * segments as f64;
+        let segment = (position.floor() as usize).min(segments - 1);
+        let t = self.easing.apply(position - segment as f64);
+
+        let (from, to) = (&self.keyframes[segment], &self.keyframes[segment + 1]);
+
+        // Exponential zoom: the log of the zoom moves linearly
+        let zoom = from.zoom * (to.zoom / from.zoom).powf(t);
+
+        // Fraction of the way the center has moved, following the view radius
+        let (r0, r1) = (1.0 / from.zoom, 1.0 / to.zoom);
+        let w = if (r0 - r1).abs() > f64::EPSILON * r0.max(r1) { (r0 - 1.0 / zoom) / (r0 - r1) } else { t };
+
+        Keyframe { center: lerp_center(&from.center, &to.center, w, zoom.max(from.zoom).max(to.zoom)), zoom }
+    }
+
+    /// Iterate over the views of every frame
+    pub fn views(&self) -> impl Iterator<Item = Keyframe> + '_ {
+        (0..self.frames).map(|i| self.frame(i))
+    }
+}
+
+/// Interpolate between two exact centers, keeping enough precision for `zoom`
+fn lerp_center(from: &DecimalComplex, to: &DecimalComplex, w: f64, zoom: f64) -> DecimalComplex {
+    if w <= 0.0 {
+        return from.clone();
+    }
+    if w >= 1.0 {
+        return to.clone();
+    }
+
+    let frac_bits = (zoom / Viewport::BASE_RADIUS).log2().max(0.0).ceil() as u32 + 64;
+    let (a_re, a_im) = from.to_fixed(frac_bits);
+    let (b_re, b_im) = to.to_fixed(frac_bits);
+    let w = Fixed::from_f64(w, frac_bits);
+
+    let re = &a_re + &(&(&b_re - &a_re) * &w);
+    let im = &a_im + &(&(&b_im - &a_im) * &w);
+    DecimalComplex::from((re, im))
+}
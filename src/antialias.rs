@@ -0,0 +1,154 @@
+//! Supersampling anti-aliasing.
+//!
+//! Each refined pixel is iterated on an N x N grid of subpixel points, either at
+//! the cell centers or jittered inside each cell (stratified sampling), and the
+//! resulting colors are averaged in linear light.
+
+use indicatif::{ParallelProgressIterator, ProgressBar};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::color::Color;
+use crate::coloring::EscapeHistogram;
+use crate::escape::iterate_point;
+use crate::formula::FractalFormula;
+use crate::image::Image;
+use crate::render::RenderParams;
+use crate::viewport::map_subpixel_to_complex;
+
+/// Where the subpixel samples are placed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Sampling {
+    /// Centers of an N x N grid
+    #[default]
+    Grid,
+    /// A random point inside each cell of an N x N grid
+    Jitter,
+}
+
+/// Supersampling settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Antialias {
+    /// Samples per pixel along each axis; 1 disables supersampling
+    pub samples: usize,
+    pub sampling: Sampling,
+    /// Only refine pixels whose color differs from a neighbour by more than this
+    /// (largest channel difference); `None` refines every pixel
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub adaptive: Option<u8>,
+    /// Seed of the jitter pattern
+    pub seed: u64,
+}
+
+impl Default for Antialias {
+    fn default() -> Antialias {
+        Antialias { samples: 1, sampling: Sampling::default(), adaptive: None, seed: 0 }
+    }
+}
+
+/// Refine `img`, rendered with one sample per pixel, according to `params.antialias`;
+/// `histogram` is the escape time distribution of the first pass, for the histogram coloring
+pub fn supersample<F: FractalFormula + ?Sized>(
+    params: &RenderParams,
+    formula: &F,
+    img: &mut Image,
+    histogram: Option<&EscapeHistogram>,
+    pb: ProgressBar,
+) {
+    let aa = params.antialias;
+    if aa.samples <= 1 {
+        return;
+    }
+
+    let targets: Vec<usize> = match aa.adaptive {
+        None => (0..img.pixels.len()).collect(),
+        Some(threshold) => edge_pixels(img, threshold),
+    };
+    pb.inc_length(targets.len() as u64);
+
+    let colors: Vec<Color> = targets
+        .par_iter()
+        .progress_with(pb)
+        .map(|&i| pixel_color(params, formula, histogram, i % img.width, i / img.width))
+        .collect();
+
+    for (i, color) in targets.into_iter().zip(colors) {
+        img.pixels[i] = color;
+    }
+}
+
+/// Average color of the subpixel samples of pixel (x, y)
+pub(crate) fn pixel_color<F: FractalFormula + ?Sized>(
+    params: &RenderParams,
+    formula: &F,
+    histogram: Option<&EscapeHistogram>,
+    x: usize,
+    y: usize,
+) -> Color {
+    let &RenderParams { width, height, viewport, julia, max_iter, ref palette, coloring, interior, antialias: aa, ref trap, .. } = params;
+    let n = aa.samples;
+    let spacing = viewport.pixel_spacing(width, height);
+
+    let mut sum = [0.0; 3];
+    for j in 0..n {
+        for i in 0..n {
+            let (dx, dy) = match aa.sampling {
+                Sampling::Grid => (0.5, 0.5),
+                Sampling::Jitter => {
+                    let h = hash(aa.seed, (y * width + x) as u64, (j * n + i) as u64);
+                    (unit(h), unit(h >> 32))
+                }
+            };
+            let sx = x as f64 + (i as f64 + dx) / n as f64;
+            let sy = y as f64 + (j as f64 + dy) / n as f64;
+
+            let p = map_subpixel_to_complex(sx, sy, width, height, &viewport);
+            let sample = iterate_point(formula, p, julia, max_iter, coloring.needs_distance(), trap.as_ref());
+            let color = coloring.color(&sample, palette, spacing, interior, histogram);
+
+            for (acc, v) in sum.iter_mut().zip(color.to_linear()) {
+                *acc += v;
+            }
+        }
+    }
+
+    let count = (n * n) as f64;
+    Color::from_linear(sum.map(|v| v / count))
+}
+
+/// Pixels differing from one of their 4 neighbours by more than `threshold`
+pub(crate) fn edge_pixels(img: &Image, threshold: u8) -> Vec<usize> {
+    let (w, h) = (img.width, img.height);
+    let differs = |a: Color, b: Color| {
+        a.r.abs_diff(b.r).max(a.g.abs_diff(b.g)).max(a.b.abs_diff(b.b)) > threshold
+    };
+
+    (0..w * h)
+        .into_par_iter()
+        .filter(|&i| {
+            let (x, y) = (i % w, i / w);
+            let p = img.pixels[i];
+            (x > 0 && differs(p, img.pixels[i - 1]))
+                || (x + 1 < w && differs(p, img.pixels[i + 1]))
+                || (y > 0 && differs(p, img.pixels[i - w]))
+                || (y + 1 < h && differs(p, img.pixels[i + w]))
+        })
+        .collect()
+}
+
+/// Deterministic per-sample random bits (SplitMix64 finalizer)
+pub(crate) fn hash(seed: u64, pixel: u64, sample: u64) -> u64 {
+    let mut z = seed
+        .wrapping_add(pixel.wrapping_mul(0x9e37_79b9_7f4a_7c15))
+        .wrapping_add(sample.wrapping_mul(0xd1b5_4a32_d192_ed03));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Map the low 32 bits to [0, 1)
+pub(crate) fn unit(bits: u64) -> f64 {
+    (bits & 0xffff_ffff) as f64 / 4_294_967_296.0
+}
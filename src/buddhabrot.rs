@@ -0,0 +1,204 @@
+//! Buddhabrot and Nebulabrot density rendering.
+//!
+//! Instead of coloring each pixel by its own escape time, random parameters c
+//! are iterated and every point of their orbits is counted in the pixel it
+//! lands in. The Buddhabrot counts the orbits that escape, the anti-Buddhabrot
+//! the ones that stay bounded; a Nebulabrot uses a different iteration limit for
+//! each of the red, green and blue channels.
+//! https://en.wikipedia.org/wiki/Buddhabrot
+
+use indicatif::ProgressBar;
+use rayon::prelude::*;
+
+use crate::antialias::{hash, unit};
+use crate::color::Color;
+use crate::complex::Complex;
+use crate::formula::{Fractal, FractalFormula};
+use crate::image::Image;
+use crate::viewport::{map_complex_to_screen, Viewport};
+
+/// Samples handled by one parallel task, and per tick of the progress bar
+const CHUNK_SIZE: u64 = 1 << 14;
+
+/// Everything needed to render a Buddhabrot
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuddhabrotParams {
+    /// Image width in pixels
+    pub width: usize,
+    /// Image height in pixels
+    pub height: usize,
+    /// Region of the complex plane shown; c is sampled over the whole escape disk
+    pub viewport: Viewport,
+    /// Iterated formula
+    pub fractal: Fractal,
+    /// Iteration limits of the red, green and blue channels; equal limits give a grayscale Buddhabrot
+    pub max_iter: [usize; 3],
+    /// Escaping orbits shorter than this are not counted, which removes the haze of quickly escaping points
+    pub min_iter: usize,
+    /// Number of random parameters c
+    pub samples: u64,
+    /// Count the orbits that stay bounded instead of the escaping ones
+    pub anti: bool,
+    /// Seed of the random parameters
+    pub seed: u64,
+    /// Brightness curve: counts are mapped through `(count / max)^(1 / gamma)`
+    pub gamma: f64,
+}
+
+impl Default for BuddhabrotParams {
+    fn default() -> BuddhabrotParams {
+        BuddhabrotParams {
+            width: 1000,
+            height: 1000,
+            viewport: Viewport::default(),
+            fractal: Fractal::default(),
+            max_iter: [1000; 3],
+            min_iter: 0,
+            samples: 10_000_000,
+            anti: false,
+            seed: 0,
+            gamma: 2.0,
+        }
+    }
+}
+
+/// Hit counts of every pixel, one per color channel
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    pub width: usize,
+    pub height: usize,
+    pub counts: Vec<[u32; 3]>,
+}
+
+impl Histogram {
+    pub fn new(width: usize, height: usize) -> Histogram {
+        Histogram { width, height, counts: vec![[0; 3]; width * height] }
+    }
+
+    /// Add the counts of another histogram of the same size
+    pub fn merge(mut self, other: &Histogram) -> Histogram {
+        for (a, b) in self.counts.iter_mut().zip(&other.counts) {
+            for k in 0..3 {
+                a[k] = a[k].saturating_add(b[k]);
+            }
+        }
+        self
+    }
+
+    /// Normalize each channel by its largest count and apply the brightness curve
+    pub fn to_image(&self, gamma: f64) -> Image {
+        let mut max = [0u32; 3];
+        for counts in &self.counts {
+            for k in 0..3 {
+                max[k] = max[k].max(counts[k]);
+            }
+        }
+
+        let level = |count: u32, max: u32| {
+            if max == 0 {
+                return 0;
+            }
+            let v = (count as f64 / max as f64).powf(1.0 / gamma);
+            (v * 255.0).round() as u8
+        };
+
+        let pixels = self
+            .counts
+            .iter()
+            .map(|c| Color::new(level(c[0], max[0]), level(c[1], max[1]), level(c[2], max[2])))
+            .collect();
+
+        Image { width: self.width, height: self.height, pixels }
+    }
+}
+
+/// Render a Buddhabrot image
+pub fn render_buddhabrot_with_progress(params: &BuddhabrotParams, pb: ProgressBar) -> Image {
+    compute_buddhabrot_with_progress(params, &params.fractal, pb).to_image(params.gamma)
+}
+
+/// Accumulate the orbit histogram with a custom formula, reporting one tick per sample on `pb`;
+/// `params.fractal` is ignored
+///
+/// The result only depends on `params.seed`, not on how the work is split between threads.
+pub fn compute_buddhabrot_with_progress<F: FractalFormula + ?Sized>(
+    params: &BuddhabrotParams,
+    formula: &F,
+    pb: ProgressBar,
+) -> Histogram {
+    let &BuddhabrotParams { width, height, samples, .. } = params;
+    pb.set_length(samples);
+
+    let chunks = samples.div_ceil(CHUNK_SIZE);
+    let histogram = (0..chunks)
+        .into_par_iter()
+        .fold(
+            // One histogram and orbit buffer per rayon task, merged at the end
+            || (Histogram::new(width, height), Vec::new()),
+            |(mut histogram, mut orbit), chunk| {
+                let end = ((chunk + 1) * CHUNK_SIZE).min(samples);
+                for i in chunk * CHUNK_SIZE..end {
+                    trace(params, formula, i, &mut orbit, &mut histogram);
+                }
+                pb.inc(end - chunk * CHUNK_SIZE);
+                (histogram, orbit)
+            },
+        )
+        .map(|(histogram, _)| histogram)
+        .reduce(|| Histogram::new(width, height), |a, b| a.merge(&b));
+
+    pb.finish();
+    histogram
+}
+
+/// Iterate the `i`-th random parameter and count its orbit in every channel it belongs to
+fn trace<F: FractalFormula + ?Sized>(
+    params: &BuddhabrotParams,
+    formula: &F,
+    i: u64,
+    orbit: &mut Vec<Complex>,
+    histogram: &mut Histogram,
+) {
+    let &BuddhabrotParams { width, height, viewport, max_iter, min_iter, anti, seed, .. } = params;
+
+    // Uniform over the square around the escape disk
+    let radius = formula.bailout();
+    let h = hash(seed, i, 0);
+    let c = Complex::new((2.0 * unit(h) - 1.0) * radius, (2.0 * unit(h >> 32) - 1.0) * radius);
+
+    // Known interior points never escape, so only the anti-Buddhabrot needs their orbits
+    if !anti && formula.known_interior(c).is_some() {
+        return;
+    }
+
+    let limit = max_iter.iter().copied().max().unwrap_or(0);
+    orbit.clear();
+
+    let mut z = if formula.starts_at_c() { c } else { Complex::ZERO };
+    let mut escaped_at = None;
+    for n in 0..limit {
+        z = formula.step(z, c);
+        if formula.escaped(z) {
+            escaped_at = Some(n);
+            break;
+        }
+        orbit.push(z);
+    }
+
+    for (k, &channel_limit) in max_iter.iter().enumerate() {
+        // Number of orbit points counted in this channel
+        let points = match (escaped_at, anti) {
+            (Some(n), false) if n < channel_limit && n >= min_iter => n,
+            (None, true) => channel_limit,
+            (Some(n), true) if n >= channel_limit => channel_limit,
+            _ => continue,
+        };
+
+        for &p in &orbit[..points] {
+            if let Some((x, y)) = map_complex_to_screen(p, width, height, &viewport) {
+                let count = &mut histogram.counts[y * width + x][k];
+                *count = count.saturating_add(1);
+            }
+        }
+    }
+}
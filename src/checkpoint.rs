@@ -0,0 +1,290 @@
+//! Resumable renders.
+//!
+//! The escape data is computed in bands of rows; every finished band is appended
+//! to a checkpoint file right away, so an interrupted render can later continue
+//! with only the missing bands.
+//!
+//! File layout, all numbers little endian:
+//!
+//! | size | content                                                  |
+//! |------|----------------------------------------------------------|
+//! | 8    | magic `b"FRACCKPT"`                                      |
+//! | 4    | format version, `u32`, currently 3                       |
+//! | 4    | length n of the parameter fingerprint, `u32`             |
+//! | n    | parameter fingerprint, UTF-8                             |
+//!
+//! followed by one block per finished band: its first row and row count as two
+//! `u32`, then one record per pixel as in the escape data format of [`crate::data`].
+//! An incomplete last block, left by a killed process, is ignored.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use indicatif::ProgressBar;
+use rayon::prelude::*;
+
+use crate::complex::Complex;
+use crate::data::{read_sample, read_u32, write_sample, EscapeData};
+use crate::escape::{iterate_point, Sample};
+use crate::fixed::DecimalComplex;
+use crate::perturbation::compute_deep_rows;
+use crate::precision::Precision;
+use crate::real::Real;
+use crate::render::RenderParams;
+use crate::viewport::{map_screen_to_complex, Viewport};
+
+const MAGIC: &[u8; 8] = b"FRACCKPT";
+const VERSION: u32 = 3;
+
+/// Approximate number of pixels per band, i.e. between two checkpoint writes
+const BAND_PIXELS: usize = 1 << 16;
+
+/// Compute the escape data of every pixel, saving progress to the checkpoint file at `path`
+///
+/// With `resume`, bands already in the checkpoint are reused after checking they
+/// were computed with the same parameters; otherwise the file is started over.
+/// When `cancel` is set the band in progress is dropped and an error of kind
+/// [`ErrorKind::Interrupted`] is returned, leaving a valid checkpoint behind.
+pub fn compute_resumable(
+    params: &RenderParams,
+    center: &DecimalComplex,
+    precision: Precision,
+    path: &Path,
+    resume: bool,
+    cancel: &AtomicBool,
+    pb: ProgressBar,
+) -> std::io::Result<EscapeData> {
+    let &RenderParams { width, height, max_iter, .. } = params;
+    let precision = precision.resolve(params);
+    let fingerprint = fingerprint(params, center, precision);
+
+    let mut samples = vec![None; width * height];
+    let mut writer = if resume {
+        let valid_len = read_checkpoint(path, &fingerprint, width, &mut samples)?;
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        file.set_len(valid_len)?;
+        file.seek(SeekFrom::End(0))?;
+        BufWriter::new(file)
+    } else {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&(fingerprint.len() as u32).to_le_bytes())?;
+        writer.write_all(fingerprint.as_bytes())?;
+        writer.flush()?;
+        writer
+    };
+
+    pb.set_length((width * height) as u64);
+    pb.set_position(samples.iter().filter(|s| s.is_some()).count() as u64);
+
+    let rows_per_band = BAND_PIXELS.div_ceil(width).max(1);
+    for start in (0..height).step_by(rows_per_band) {
+        let rows = start..(start + rows_per_band).min(height);
+        if samples[rows.start * width..rows.end * width].iter().all(|s| s.is_some()) {
+            continue;
+        }
+
+        let band = match precision {
+            Precision::Perturbation => {
+                compute_deep_rows(params, center, rows.clone(), cancel, &pb).map_err(std::io::Error::other)?
+            }
+            Precision::DoubleDouble => compute_rows(params, center.to_double_double(), rows.clone(), cancel, &pb),
+            Precision::Auto | Precision::F64 => compute_rows(params, params.viewport.center, rows.clone(), cancel, &pb),
+        };
+        let Some(band) = band else {
+            writer.flush()?;
+            return Err(std::io::Error::new(ErrorKind::Interrupted, "render interrupted"));
+        };
+
+        writer.write_all(&(rows.start as u32).to_le_bytes())?;
+        writer.write_all(&(rows.len() as u32).to_le_bytes())?;
+        for sample in &band {
+            write_sample(&mut writer, sample)?;
+        }
+        writer.flush()?;
+
+        for (slot, sample) in samples[rows.start * width..].iter_mut().zip(band) {
+            *slot = Some(sample);
+        }
+    }
+
+    pb.finish();
+
+    let pixel_spacing = params.viewport.pixel_spacing(width, height);
+    let samples = samples.into_iter().map(|s| s.expect("every band is computed")).collect();
+    Ok(EscapeData { width, height, max_iter, pixel_spacing, samples })
+}
+
+/// Everything the escape data depends on; colors can change between runs
+fn fingerprint(params: &RenderParams, center: &DecimalComplex, precision: Precision) -> String {
+    let &RenderParams { width, height, viewport, ref fractal, julia, max_iter, coloring, ref trap, .. } = params;
+    format!(
+        "size={width}x{height} center={center} radius={:?} fractal={fractal:?} julia={julia:?} \
+         max_iter={max_iter} distance={} trap={trap:?} precision={precision:?}",
+        viewport.radius,
+        coloring.needs_distance(),
+    )
+}
+
+/// Load the finished bands of a checkpoint into `samples`; returns the length of the valid part of the file
+fn read_checkpoint(path: &Path, fingerprint: &str, width: usize, samples: &mut [Option<Sample>]) -> std::io::Result<u64> {
+    let invalid = |msg: String| std::io::Error::new(ErrorKind::InvalidData, msg);
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid(format!("{} is not a render checkpoint", path.display())));
+    }
+    let version = read_u32(&mut reader)?;
+    if version != VERSION {
+        return Err(invalid(format!("unsupported checkpoint version {version}")));
+    }
+
+    let mut stored = vec![0; read_u32(&mut reader)? as usize];
+    reader.read_exact(&mut stored)?;
+    if stored != fingerprint.as_bytes() {
+        return Err(invalid(format!(
+            "the checkpoint was written for other render parameters:\n  checkpoint: {}\n  this render: {fingerprint}",
+            String::from_utf8_lossy(&stored),
+        )));
+    }
+
+    let mut valid_len = reader.stream_position()?;
+    while let Ok(Some((rows, band))) = read_band(&mut reader, width, samples.len()) {
+        for (slot, sample) in samples[rows.start * width..].iter_mut().zip(band) {
+            *slot = Some(sample);
+        }
+        valid_len = reader.stream_position()?;
+    }
+
+    Ok(valid_len)
+}
+
+/// Read one band block, `None` at the end of the file
+fn read_band<R: Read>(reader: &mut R, width: usize, pixels: usize) -> std::io::Result<Option<(Range<usize>, Vec<Sample>)>> {
+    let start = match read_u32(reader) {
+        Ok(start) => start as usize,
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let rows = start..start + read_u32(reader)? as usize;
+    if rows.end * width > pixels {
+        return Err(std::io::Error::new(ErrorKind::InvalidData, "checkpoint band outside the image"));
+    }
+
+    let band = (0..rows.len() * width)
+        .map(|_| read_sample(reader))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    Ok(Some((rows, band)))
+}
+
+/// Samples of the pixels in `rows` around `center`, in its float type; `None` if cancelled first
+fn compute_rows<T: Real>(
+    params: &RenderParams,
+    center: Complex<T>,
+    rows: Range<usize>,
+    cancel: &AtomicBool,
+    pb: &ProgressBar,
+) -> Option<Vec<Sample>> {
+    let &RenderParams { width, height, ref fractal, julia, max_iter, coloring, ref trap, .. } = params;
+    let viewport = Viewport { center, radius: params.viewport.radius };
+    let julia = julia.map(Complex::cast);
+
+    (rows.start * width..rows.end * width)
+        .into_par_iter()
+        .map(|i| {
+            if cancel.load(Ordering::Relaxed) {
+                return None;
+            }
+            let p = map_screen_to_complex(i % width, i / width, width, height, &viewport);
+            let sample = iterate_point(fractal, p, julia, max_iter, coloring.needs_distance(), trap.as_ref());
+            pb.inc(1);
+            Some(sample)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::render::compute_with_progress;
+
+    /// Four bands of the whole Mandelbrot set
+    fn params() -> RenderParams {
+        RenderParams { width: 256, height: 1024, max_iter: 100, ..RenderParams::default() }
+    }
+
+    fn center() -> DecimalComplex {
+        "-0.5,0".parse().unwrap()
+    }
+
+    /// A checkpoint file of its own for each test
+    fn checkpoint(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("fractal-{name}-{}.ckpt", std::process::id()))
+    }
+
+    fn compute(params: &RenderParams, path: &Path, resume: bool) -> std::io::Result<EscapeData> {
+        let never = AtomicBool::new(false);
+        compute_resumable(params, &center(), Precision::F64, path, resume, &never, ProgressBar::hidden())
+    }
+
+    #[test]
+    fn resumes_after_cancel() {
+        let (params, path) = (params(), checkpoint("cancel"));
+        let cancel = AtomicBool::new(false);
+        let pb = ProgressBar::hidden();
+
+        let interrupted = thread::scope(|scope| {
+            scope.spawn(|| {
+                while pb.position() < 100_000 {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                cancel.store(true, Ordering::Relaxed);
+            });
+            compute_resumable(&params, &center(), Precision::F64, &path, false, &cancel, pb.clone())
+        });
+        assert_eq!(interrupted.unwrap_err().kind(), ErrorKind::Interrupted);
+        let header = (MAGIC.len() + 8 + fingerprint(&params, &center(), Precision::F64).len()) as u64;
+        assert!(std::fs::metadata(&path).unwrap().len() > header, "no band was saved");
+
+        let resumed = compute(&params, &path, true).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(resumed, compute_with_progress(&params, ProgressBar::hidden()));
+    }
+
+    #[test]
+    fn drops_a_truncated_band() {
+        let (params, path) = (params(), checkpoint("truncated"));
+        let full = compute(&params, &path, false).unwrap();
+
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        let len = file.metadata().unwrap().len();
+        file.set_len(len - 100).unwrap();
+
+        let resumed = compute(&params, &path, true).unwrap();
+        let repaired = std::fs::metadata(&path).unwrap().len();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(resumed, full);
+        assert_eq!(repaired, len);
+    }
+
+    #[test]
+    fn rejects_other_parameters() {
+        let (params, path) = (params(), checkpoint("mismatch"));
+        compute(&params, &path, false).unwrap();
+
+        let error = compute(&RenderParams { max_iter: 200, ..params }, &path, true).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(error.to_string().contains("other render parameters"), "{error}");
+    }
+}
@@ -0,0 +1,153 @@
+use std::str::FromStr;
+
+/// An 8-bit sRGB color
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const BLACK: Color = Color { r: 0, g: 0, b: 0 };
+    pub const WHITE: Color = Color { r: 255, g: 255, b: 255 };
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Color {
+        Color { r, g, b }
+    }
+
+    /// Convert to linear-light RGB in [0, 1]
+    pub fn to_linear(self) -> [f64; 3] {
+        [self.r, self.g, self.b].map(|v| srgb_to_linear(v as f64 / 255.0))
+    }
+
+    /// Convert from linear-light RGB, clamping out of gamut values
+    pub fn from_linear(rgb: [f64; 3]) -> Color {
+        let [r, g, b] = rgb.map(|v| (linear_to_srgb(v.clamp(0.0, 1.0)) * 255.0).round() as u8);
+        Color { r, g, b }
+    }
+
+    /// Convert to the Oklab perceptual color space
+    pub fn to_oklab(self) -> Oklab {
+        let [r, g, b] = self.to_linear();
+
+        let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+        let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+        let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+
+        Oklab {
+            l: 0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+            a: 1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+            b: 0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+        }
+    }
+}
+
+impl std::fmt::Display for Color {
+    /// Written as "#rrggbb"
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    /// Parse a hex color written as "#rrggbb" or "rrggbb"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.trim().trim_start_matches('#');
+        if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(format!("expected a color like \"#rrggbb\", got \"{s}\""));
+        }
+        let channel = |i: usize| {
+            u8::from_str_radix(&hex[i..i + 2], 16).map_err(|e| format!("invalid color \"{s}\": {e}"))
+        };
+        Ok(Color { r: channel(0)?, g: channel(2)?, b: channel(4)? })
+    }
+}
+
+/// A color in the Oklab perceptual color space
+/// https://bottosson.github.io/posts/oklab/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Oklab {
+    pub l: f64,
+    pub a: f64,
+    pub b: f64,
+}
+
+impl Oklab {
+    /// Linear interpolation between two colors, `t` in [0, 1]
+    pub fn lerp(self, other: Oklab, t: f64) -> Oklab {
+        Oklab {
+            l: self.l + (other.l - self.l) * t,
+            a: self.a + (other.a - self.a) * t,
+            b: self.b + (other.b - self.b) * t,
+        }
+    }
+
+    pub fn to_color(self) -> Color {
+        let l = self.l + 0.3963377774 * self.a + 0.2158037573 * self.b;
+        let m = self.l - 0.1055613458 * self.a - 0.0638541728 * self.b;
+        let s = self.l - 0.0894841775 * self.a - 1.2914855480 * self.b;
+
+        let (l, m, s) = (l * l * l, m * m * m, s * s * s);
+
+        Color::from_linear([
+            4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+            -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+            -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
+        ])
+    }
+}
+
+fn srgb_to_linear(v: f64) -> f64 {
+    if v <= 0.04045 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) }
+}
+
+fn linear_to_srgb(v: f64) -> f64 {
+    if v <= 0.0031308 { v * 12.92 } else { 1.055 * v.powf(1.0 / 2.4) - 0.055 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_colors() {
+        assert_eq!("#ff8000".parse(), Ok(Color::new(255, 128, 0)));
+        assert_eq!("0A0b0C".parse(), Ok(Color::new(10, 11, 12)));
+        assert_eq!(" #ffffff ".parse(), Ok(Color::WHITE));
+        assert_eq!(Color::new(255, 128, 0).to_string(), "#ff8000");
+    }
+
+    #[test]
+    fn rejects_malformed_hex_colors() {
+        // from_str_radix alone would read "+f" as 15
+        for s in ["#+f+f+f", "#fff", "#gg0000", "#ff80001", "", "#"] {
+            assert!(s.parse::<Color>().is_err(), "{s:?}");
+        }
+    }
+
+    #[test]
+    fn oklab_round_trip() {
+        let levels = [0, 1, 17, 64, 127, 128, 200, 254, 255];
+        for r in levels {
+            for g in levels {
+                for b in levels {
+                    let color = Color::new(r, g, b);
+                    assert_eq!(color.to_oklab().to_color(), color);
+                    assert_eq!(Color::from_linear(color.to_linear()), color);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn oklab_of_gray_has_no_hue() {
+        let white = Color::WHITE.to_oklab();
+        assert!((white.l - 1.0).abs() < 1e-6 && white.a.abs() < 1e-6 && white.b.abs() < 1e-6, "{white:?}");
+        let gray = Color::new(128, 128, 128).to_oklab();
+        assert!(gray.a.abs() < 1e-6 && gray.b.abs() < 1e-6, "{gray:?}");
+        assert_eq!(Color::BLACK.to_oklab().l, 0.0);
+    }
+}
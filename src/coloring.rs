@@ -0,0 +1,178 @@
+//! How raw escape samples are turned into colors.
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::color::Color;
+use crate::escape::Sample;
+use crate::palette::Palette;
+use crate::trap::TrapHit;
+
+/// Coloring mode
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "kebab-case")]
+pub enum Coloring {
+    /// Palette over the smooth escape time
+    #[default]
+    EscapeTime,
+    /// Palette over the distance to the set: one palette cycle per 8 doublings
+    /// of the distance in pixels at density 1
+    Distance,
+    /// Escape time palette darkened within `width` pixels of the boundary
+    Shaded {
+        #[serde(default = "default_width")]
+        width: f64,
+    },
+    /// Black boundary lines, `width` pixels thick, on a white background
+    LineArt {
+        #[serde(default = "default_width")]
+        width: f64,
+    },
+    /// Palette over the rank of the escape time among all pixels (histogram
+    /// equalization): one palette cycle over the whole image at density 1
+    Histogram,
+    /// Palette over the smallest distance from the orbit to the orbit trap, one
+    /// palette cycle per unit of distance at density 1, or the color an image
+    /// trap caught; inside the set too
+    Trap,
+}
+
+/// Coloring of the points that never escape
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Interior {
+    #[default]
+    Black,
+    /// Palette over the final |z|: one palette cycle per unit at density 1
+    Magnitude,
+    /// One palette color per period of the attracting cycle
+    Period,
+    /// Palette over the interior distance estimate, as the distance coloring
+    /// does outside; parameter plane only
+    Distance,
+    /// Palette over the angle of the cycle multiplier, darkening as its
+    /// magnitude approaches 1 at the edge of each hyperbolic component
+    Multiplier,
+}
+
+/// Palette position between neighbouring periods, in palette cycles; the
+/// golden angle keeps any few consecutive periods far apart on the palette
+const PERIOD_STEP: f64 = 0.381_966_011_250_105;
+
+impl Interior {
+    /// Color a sample that did not escape; black when it lacks what the mode needs
+    pub fn color(&self, sample: &Sample, palette: &Palette, pixel_spacing: f64) -> Color {
+        match *self {
+            Interior::Black => Color::BLACK,
+            Interior::Magnitude => palette.color(sample.z.magnitude() * Palette::PERIOD),
+            Interior::Period => match sample.period {
+                Some(period) => palette.color(period as f64 * PERIOD_STEP * Palette::PERIOD),
+                None => Color::BLACK,
+            },
+            Interior::Distance => match sample.distance {
+                Some(d) => palette.color((d / pixel_spacing + 1.0).log2() * Palette::PERIOD / 8.0),
+                None => Color::BLACK,
+            },
+            Interior::Multiplier => match sample.multiplier {
+                Some(m) => {
+                    let turns = m.im.atan2(m.re) / std::f64::consts::TAU + 0.5;
+                    let shade = 1.0 - m.magnitude_squared().min(1.0);
+                    let [r, g, b] = palette.color(turns * Palette::PERIOD).to_linear();
+                    Color::from_linear([r * shade, g * shade, b * shade])
+                }
+                None => Color::BLACK,
+            },
+        }
+    }
+}
+
+/// Boundary width when a scene file does not give one
+fn default_width() -> f64 {
+    1.0
+}
+
+impl Coloring {
+    /// Whether the samples need the exterior distance estimate
+    pub fn needs_distance(&self) -> bool {
+        !matches!(self, Coloring::EscapeTime | Coloring::Histogram | Coloring::Trap)
+    }
+
+    /// Whether colors depend on the escape times of the whole image
+    pub fn needs_histogram(&self) -> bool {
+        matches!(self, Coloring::Histogram)
+    }
+
+    /// Color a sample; `pixel_spacing` converts distances to pixels, points
+    /// that did not escape are colored by `interior`, and the histogram coloring
+    /// ranks escape times in `histogram`
+    pub fn color(
+        &self,
+        sample: &Sample,
+        palette: &Palette,
+        pixel_spacing: f64,
+        interior: Interior,
+        histogram: Option<&EscapeHistogram>,
+    ) -> Color {
+        if let (Coloring::Trap, Some(hit)) = (self, sample.trap) {
+            return match hit {
+                TrapHit::Distance(d) => palette.color(d * Palette::PERIOD),
+                TrapHit::Color(color) => color,
+            };
+        }
+
+        let Some(escape_time) = sample.escape_time else {
+            return interior.color(sample, palette, pixel_spacing);
+        };
+        let pixels = sample.distance.map(|d| d / pixel_spacing);
+
+        if let (Coloring::Histogram, Some(histogram)) = (self, histogram) {
+            return palette.color(histogram.rank(escape_time) * Palette::PERIOD);
+        }
+
+        match (*self, pixels) {
+            (Coloring::Distance, Some(d)) => palette.color((d + 1.0).log2() * Palette::PERIOD / 8.0),
+            (Coloring::Shaded { width }, Some(d)) => {
+                let shade = (d / width).clamp(0.0, 1.0);
+                let [r, g, b] = palette.color(escape_time).to_linear();
+                Color::from_linear([r * shade, g * shade, b * shade])
+            }
+            (Coloring::LineArt { width }, Some(d)) => {
+                if d < width { Color::BLACK } else { Color::WHITE }
+            }
+            (Coloring::LineArt { .. }, None) => Color::WHITE,
+            _ => palette.color(escape_time),
+        }
+    }
+}
+
+/// Cumulative distribution of the escape times of an image, for histogram equalization
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct EscapeHistogram {
+    /// Escape times of the escaped samples, in increasing order
+    times: Vec<f64>,
+}
+
+impl EscapeHistogram {
+    pub fn new<'a>(samples: impl IntoIterator<Item = &'a Sample>) -> EscapeHistogram {
+        let mut times: Vec<f64> = samples.into_iter().filter_map(|s| s.escape_time).filter(|t| t.is_finite()).collect();
+        times.par_sort_unstable_by(f64::total_cmp);
+        EscapeHistogram { times }
+    }
+
+    /// Fraction in [0, 1] of the escape times below `escape_time`, interpolated
+    /// linearly between neighbouring values so bands stay smooth
+    pub fn rank(&self, escape_time: f64) -> f64 {
+        let n = self.times.len();
+        let i = self.times.partition_point(|&t| t < escape_time);
+        if n < 2 || i == 0 {
+            return 0.0;
+        }
+        if i == n {
+            return 1.0;
+        }
+
+        let (lo, hi) = (self.times[i - 1], self.times[i]);
+        let f = if hi > lo { (escape_time - lo) / (hi - lo) } else { 1.0 };
+        ((i - 1) as f64 + f) / (n - 1) as f64
+    }
+}
@@ -0,0 +1,318 @@
+use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::real::Real;
+
+/// A complex number, with `f64` components unless another [`Real`] type is given
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Complex<T = f64> {
+    pub re: T,
+    pub im: T,
+}
+
+impl<T: Real> Complex<T> {
+    pub const ZERO: Complex<T> = Complex { re: T::ZERO, im: T::ZERO };
+    pub const ONE: Complex<T> = Complex { re: T::ONE, im: T::ZERO };
+    pub const I: Complex<T> = Complex { re: T::ZERO, im: T::ONE };
+
+    pub fn new(re: T, im: T) -> Complex<T> {
+        Complex { re, im }
+    }
+
+    /// Convert both parts to another float type
+    pub fn cast<U: Real>(self) -> Complex<U> {
+        Complex { re: U::from_f64(self.re.to_f64()), im: U::from_f64(self.im.to_f64()) }
+    }
+
+    pub fn magnitude(&self) -> T {
+        self.magnitude_squared().sqrt()
+    }
+
+    pub fn magnitude_squared(&self) -> T {
+        self.re * self.re + self.im * self.im
+    }
+
+    /// Argument in (-pi, pi]
+    pub fn arg(&self) -> T {
+        self.im.atan2(self.re)
+    }
+
+    pub fn square(&self) -> Complex<T> {
+        let re_im = self.re * self.im;
+        Complex {
+            re: self.re * self.re - self.im * self.im,
+            im: re_im + re_im,
+        }
+    }
+
+    /// Complex conjugate
+    pub fn conj(&self) -> Complex<T> {
+        Complex { re: self.re, im: -self.im }
+    }
+
+    /// Raise to an integer power by repeated squaring
+    pub fn powi(&self, n: i32) -> Complex<T> {
+        let mut base = if n < 0 { self.recip() } else { *self };
+        let mut exp = n.unsigned_abs();
+        let mut acc = Complex::ONE;
+        while exp > 0 {
+            if exp & 1 == 1 {
+                acc = acc * base;
+            }
+            base = base.square();
+            exp >>= 1;
+        }
+        acc
+    }
+
+    /// Raise to a real power using the principal branch
+    pub fn powf(&self, p: T) -> Complex<T> {
+        if self.re == T::ZERO && self.im == T::ZERO {
+            return Complex::ZERO;
+        }
+        let r = self.magnitude().powf(p);
+        let (sin, cos) = (self.arg() * p).sin_cos();
+        Complex { re: r * cos, im: r * sin }
+    }
+
+    /// Raise to a complex power using the principal branch of the logarithm
+    pub fn powc(&self, p: Complex<T>) -> Complex<T> {
+        if self.re == T::ZERO && self.im == T::ZERO {
+            return Complex::ZERO;
+        }
+        (self.ln() * p).exp()
+    }
+
+    /// Principal square root
+    pub fn sqrt(&self) -> Complex<T> {
+        let r = self.magnitude();
+        let half = T::from_f64(0.5);
+        let re = ((r + self.re) * half).sqrt();
+        let im = ((r - self.re) * half).sqrt();
+        Complex { re, im: im.copysign(self.im) }
+    }
+
+    /// Multiplicative inverse
+    pub fn recip(&self) -> Complex<T> {
+        let d = self.magnitude_squared();
+        Complex { re: self.re / d, im: -self.im / d }
+    }
+
+    pub fn exp(&self) -> Complex<T> {
+        let r = self.re.exp();
+        let (sin, cos) = self.im.sin_cos();
+        Complex { re: r * cos, im: r * sin }
+    }
+
+    /// Principal natural logarithm
+    pub fn ln(&self) -> Complex<T> {
+        Complex { re: self.magnitude().ln(), im: self.arg() }
+    }
+
+    pub fn sin(&self) -> Complex<T> {
+        // sin(a + bi) = sin a cosh b + i cos a sinh b
+        let (sin, cos) = self.re.sin_cos();
+        let (cosh, sinh) = cosh_sinh(self.im);
+        Complex { re: sin * cosh, im: cos * sinh }
+    }
+
+    pub fn cos(&self) -> Complex<T> {
+        // cos(a + bi) = cos a cosh b - i sin a sinh b
+        let (sin, cos) = self.re.sin_cos();
+        let (cosh, sinh) = cosh_sinh(self.im);
+        Complex { re: cos * cosh, im: -(sin * sinh) }
+    }
+}
+
+/// cosh x and sinh x from one exponential
+fn cosh_sinh<T: Real>(x: T) -> (T, T) {
+    let e = x.exp();
+    let inv = T::ONE / e;
+    let half = T::from_f64(0.5);
+    ((e + inv) * half, (e - inv) * half)
+}
+
+impl<T: Real> Add for Complex<T> {
+    type Output = Complex<T>;
+
+    fn add(self, other: Complex<T>) -> Complex<T> {
+        Complex {
+            re: self.re + other.re,
+            im: self.im + other.im,
+        }
+    }
+}
+
+impl<T: Real> Sub for Complex<T> {
+    type Output = Complex<T>;
+
+    fn sub(self, other: Complex<T>) -> Complex<T> {
+        Complex {
+            re: self.re - other.re,
+            im: self.im - other.im,
+        }
+    }
+}
+
+impl<T: Real> Mul for Complex<T> {
+    type Output = Complex<T>;
+
+    fn mul(self, other: Complex<T>) -> Complex<T> {
+        Complex {
+            re: self.re * other.re - self.im * other.im,
+            im: self.re * other.im + self.im * other.re,
+        }
+    }
+}
+
+impl<T: Real> Div for Complex<T> {
+    type Output = Complex<T>;
+
+    fn div(self, other: Complex<T>) -> Complex<T> {
+        let d = other.magnitude_squared();
+        Complex {
+            re: (self.re * other.re + self.im * other.im) / d,
+            im: (self.im * other.re - self.re * other.im) / d,
+        }
+    }
+}
+
+impl<T: Real> Neg for Complex<T> {
+    type Output = Complex<T>;
+
+    fn neg(self) -> Complex<T> {
+        Complex { re: -self.re, im: -self.im }
+    }
+}
+
+impl<T: Real> Add<T> for Complex<T> {
+    type Output = Complex<T>;
+
+    fn add(self, k: T) -> Complex<T> {
+        Complex { re: self.re + k, im: self.im }
+    }
+}
+
+impl<T: Real> Sub<T> for Complex<T> {
+    type Output = Complex<T>;
+
+    fn sub(self, k: T) -> Complex<T> {
+        Complex { re: self.re - k, im: self.im }
+    }
+}
+
+impl<T: Real> Mul<T> for Complex<T> {
+    type Output = Complex<T>;
+
+    fn mul(self, k: T) -> Complex<T> {
+        Complex { re: self.re * k, im: self.im * k }
+    }
+}
+
+impl<T: Real> Div<T> for Complex<T> {
+    type Output = Complex<T>;
+
+    fn div(self, k: T) -> Complex<T> {
+        Complex { re: self.re / k, im: self.im / k }
+    }
+}
+
+impl<T: Real> std::fmt::Display for Complex<T> {
+    /// Written as "re,im", the form accepted by `FromStr`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{}", self.re, self.im)
+    }
+}
+
+impl FromStr for Complex {
+    type Err = String;
+
+    /// Parse a complex number written as "re,im"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (re, im) = s
+            .split_once(',')
+            .ok_or_else(|| format!("expected \"re,im\", got \"{s}\""))?;
+        let re = re.trim().parse::<f64>().map_err(|e| format!("invalid real part: {e}"))?;
+        let im = im.trim().parse::<f64>().map_err(|e| format!("invalid imaginary part: {e}"))?;
+        Ok(Complex { re, im })
+    }
+}
+
+impl Serialize for Complex {
+    /// Serialized as the "re,im" string
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Complex {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Complex, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::double::DoubleDouble;
+
+    fn assert_close<T: Real>(actual: Complex<T>, expected: Complex<T>, tolerance: f64) {
+        let error = (actual - expected).magnitude().to_f64();
+        assert!(error <= tolerance * expected.magnitude().to_f64().max(1.0), "{actual} is not {expected}");
+    }
+
+    #[test]
+    fn powers_agree_with_products() {
+        let z = Complex::new(0.3, -1.2);
+        assert_close(z.powi(0), Complex::ONE, 0.0);
+        assert_close(z.powi(5), z * z * z * z * z, 1e-15);
+        assert_close(z.powi(-3), Complex::ONE / (z * z * z), 1e-15);
+        assert_close(z.powf(2.0), z.square(), 1e-15);
+        assert_close(z.powf(0.5), z.sqrt(), 1e-15);
+        assert_close(z.powc(Complex::new(3.0, 0.0)), z * z * z, 1e-15);
+        assert_close(z.sqrt().square(), z, 1e-15);
+        assert_eq!(Complex::new(-4.0, -0.0).sqrt(), Complex::new(0.0, -2.0));
+    }
+
+    #[test]
+    fn functions_satisfy_identities() {
+        let z = Complex::new(-0.7, 0.4);
+        assert_close(z.ln().exp(), z, 1e-15);
+        assert_close(z.sin().square() + z.cos().square(), Complex::ONE, 1e-15);
+        assert_close(Complex::I.square(), -Complex::<f64>::ONE, 0.0);
+        assert_close((Complex::I * std::f64::consts::PI).exp(), -Complex::<f64>::ONE, 1e-15);
+        assert_close(z * z.recip(), Complex::ONE, 1e-15);
+    }
+
+    #[test]
+    fn double_double_agrees_with_f64() {
+        let z = Complex::new(0.3, -1.2);
+        let dd: Complex<DoubleDouble> = z.cast();
+        assert_close(dd.powi(7).cast(), z.powi(7), 1e-15);
+        assert_close(dd.powf(DoubleDouble::new(2.5)).cast(), z.powf(2.5), 1e-15);
+        assert_close(dd.exp().cast(), z.exp(), 1e-15);
+        assert_close(dd.ln().cast(), z.ln(), 1e-15);
+        assert_close(dd.sin().cast(), z.sin(), 1e-15);
+        assert_close(dd.cos().cast(), z.cos(), 1e-15);
+        assert_close(dd.sqrt().cast(), z.sqrt(), 1e-15);
+    }
+
+    #[test]
+    fn casts_round_trip() {
+        let z = Complex::new(0.1, -1e-300);
+        assert_eq!(z.cast::<DoubleDouble>().cast::<f64>(), z);
+        assert_eq!(z.cast::<f32>(), Complex::new(0.1f32, -0.0));
+        assert_eq!(Complex::new(0.5f32, 2.0).cast::<f64>(), Complex::new(0.5, 2.0));
+    }
+
+    #[test]
+    fn parses_and_prints() {
+        let z: Complex = " -0.75 , 0.1 ".parse().unwrap();
+        assert_eq!(z, Complex::new(-0.75, 0.1));
+        assert_eq!(z.to_string().parse::<Complex>(), Ok(z));
+        assert!("0.5".parse::<Complex>().is_err());
+        assert!("a,1".parse::<Complex>().is_err());
+    }
+}
@@ -0,0 +1,185 @@
+//! Raw per-pixel escape data, so images can be recolored without iterating again.
+//!
+//! File layout, all numbers little endian:
+//!
+//! | offset | size | content                                   |
+//! |--------|------|-------------------------------------------|
+//! | 0      | 8    | magic `b"FRACDATA"`                       |
+//! | 8      | 4    | format version, `u32`, currently 1        |
+//! | 12     | 4    | width, `u32`                              |
+//! | 16     | 4    | height, `u32`                             |
+//! | 20     | 8    | maximum iteration count, `u64`            |
+//! | 28     | 8    | pixel spacing in the complex plane, `f64` |
+//! | 36     | 64n  | one record per pixel, row by row          |
+//!
+//! Each record holds, in order:
+//!
+//! - the smooth escape time, `f64`, NaN when the point did not escape
+//! - the real and imaginary parts of the final z, two `f64`
+//! - the detected cycle period, `u32`, 0 when none was found
+//! - the exterior or interior distance estimate in the complex plane, `f64`, NaN when unknown
+//! - the real and imaginary parts of the cycle multiplier, two `f64`, NaN when unknown
+//! - the orbit trap distance, `f64`, NaN when the trap was missed or is an image
+//! - the orbit trap color as `0x01rrggbb`, `u32`, 0 when no image pixel was hit
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use rayon::prelude::*;
+
+use crate::coloring::{Coloring, EscapeHistogram, Interior};
+use crate::complex::Complex;
+use crate::color::Color;
+use crate::escape::Sample;
+use crate::image::Image;
+use crate::palette::Palette;
+use crate::trap::TrapHit;
+
+const MAGIC: &[u8; 8] = b"FRACDATA";
+const VERSION: u32 = 1;
+
+/// Escape data for every pixel of an image, stored row by row
+#[derive(Debug, Clone, PartialEq)]
+pub struct EscapeData {
+    pub width: usize,
+    pub height: usize,
+    /// Iteration limit the data was computed with
+    pub max_iter: usize,
+    /// Distance between neighbouring pixels in the complex plane
+    pub pixel_spacing: f64,
+    pub samples: Vec<Sample>,
+}
+
+impl EscapeData {
+    /// Color every pixel
+    pub fn colorize(&self, palette: &Palette, coloring: &Coloring, interior: Interior) -> Image {
+        let histogram = self.histogram(coloring);
+        let pixels = self
+            .samples
+            .par_iter()
+            .map(|sample| coloring.color(sample, palette, self.pixel_spacing, interior, histogram.as_ref()))
+            .collect();
+        Image { width: self.width, height: self.height, pixels }
+    }
+
+    /// Escape time distribution of the image, when `coloring` needs one
+    pub fn histogram(&self, coloring: &Coloring) -> Option<EscapeHistogram> {
+        coloring.needs_histogram().then(|| EscapeHistogram::new(&self.samples))
+    }
+
+    /// Serialize in the format described in the module documentation
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&(self.width as u32).to_le_bytes())?;
+        writer.write_all(&(self.height as u32).to_le_bytes())?;
+        writer.write_all(&(self.max_iter as u64).to_le_bytes())?;
+        writer.write_all(&self.pixel_spacing.to_le_bytes())?;
+
+        for sample in &self.samples {
+            write_sample(writer, sample)?;
+        }
+        Ok(())
+    }
+
+    /// Deserialize from the format described in the module documentation
+    pub fn read_from<R: Read>(reader: &mut R) -> std::io::Result<EscapeData> {
+        let invalid = |msg: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, msg.to_string());
+
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a fractal escape data file"));
+        }
+        let version = read_u32(reader)?;
+        if version != VERSION {
+            return Err(invalid(&format!("unsupported escape data version {version}")));
+        }
+
+        let width = read_u32(reader)? as usize;
+        let height = read_u32(reader)? as usize;
+        let max_iter = read_u64(reader)? as usize;
+        let pixel_spacing = read_f64(reader)?;
+
+        let samples = (0..width * height)
+            .map(|_| read_sample(reader))
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        Ok(EscapeData { width, height, max_iter, pixel_spacing, samples })
+    }
+}
+
+/// Write escape data into a file
+pub fn write_data<P: AsRef<Path>>(filename: P, data: &EscapeData) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(filename)?);
+    data.write_to(&mut writer)?;
+    writer.flush()
+}
+
+/// Read escape data from a file
+pub fn read_data<P: AsRef<Path>>(filename: P) -> std::io::Result<EscapeData> {
+    EscapeData::read_from(&mut BufReader::new(File::open(filename)?))
+}
+
+/// Write one pixel record
+pub(crate) fn write_sample<W: Write>(writer: &mut W, sample: &Sample) -> std::io::Result<()> {
+    writer.write_all(&sample.escape_time.unwrap_or(f64::NAN).to_le_bytes())?;
+    writer.write_all(&sample.z.re.to_le_bytes())?;
+    writer.write_all(&sample.z.im.to_le_bytes())?;
+    writer.write_all(&(sample.period.unwrap_or(0) as u32).to_le_bytes())?;
+    writer.write_all(&sample.distance.unwrap_or(f64::NAN).to_le_bytes())?;
+    let multiplier = sample.multiplier.unwrap_or(Complex::new(f64::NAN, f64::NAN));
+    writer.write_all(&multiplier.re.to_le_bytes())?;
+    writer.write_all(&multiplier.im.to_le_bytes())?;
+    let (trap_distance, trap_color) = match sample.trap {
+        Some(TrapHit::Distance(d)) => (d, 0),
+        Some(TrapHit::Color(c)) => (f64::NAN, TRAP_COLOR_PRESENT | u32::from_be_bytes([0, c.r, c.g, c.b])),
+        None => (f64::NAN, 0),
+    };
+    writer.write_all(&trap_distance.to_le_bytes())?;
+    writer.write_all(&trap_color.to_le_bytes())
+}
+
+/// Set in the stored trap color when an image pixel was hit
+const TRAP_COLOR_PRESENT: u32 = 1 << 24;
+
+/// Read one pixel record
+pub(crate) fn read_sample<R: Read>(reader: &mut R) -> std::io::Result<Sample> {
+    let escape_time = read_f64(reader)?;
+    let z = Complex::new(read_f64(reader)?, read_f64(reader)?);
+    let period = read_u32(reader)? as usize;
+    let distance = read_f64(reader)?;
+    let multiplier = Complex::new(read_f64(reader)?, read_f64(reader)?);
+    let (trap_distance, trap_color) = (read_f64(reader)?, read_u32(reader)?);
+    let trap = if trap_color & TRAP_COLOR_PRESENT != 0 {
+        let [_, r, g, b] = trap_color.to_be_bytes();
+        Some(TrapHit::Color(Color::new(r, g, b)))
+    } else {
+        (!trap_distance.is_nan()).then_some(TrapHit::Distance(trap_distance))
+    };
+    Ok(Sample {
+        escape_time: (!escape_time.is_nan()).then_some(escape_time),
+        z,
+        period: (period > 0).then_some(period),
+        distance: (!distance.is_nan()).then_some(distance),
+        multiplier: (!multiplier.re.is_nan()).then_some(multiplier),
+        trap,
+    })
+}
+
+pub(crate) fn read_u32<R: Read>(reader: &mut R) -> std::io::Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> std::io::Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_f64<R: Read>(reader: &mut R) -> std::io::Result<f64> {
+    read_u64(reader).map(f64::from_bits)
+}
@@ -0,0 +1,382 @@
+//! Double-double arithmetic: a number is the unevaluated sum of two `f64`,
+//! which gives about 106 bits of mantissa at a few times the cost of `f64`.
+//! https://en.wikipedia.org/wiki/Quadruple-precision_floating-point_format#Double-double_arithmetic
+
+use std::cmp::Ordering;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use crate::real::Real;
+
+/// `hi + lo` with |lo| at most half an ulp of `hi`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DoubleDouble {
+    pub hi: f64,
+    pub lo: f64,
+}
+
+/// ln 2 and 2 pi to double-double precision
+const LN_2: DoubleDouble = DoubleDouble { hi: std::f64::consts::LN_2, lo: 2.3190468138462996e-17 };
+const TAU: DoubleDouble = DoubleDouble { hi: std::f64::consts::TAU, lo: 2.4492935982947064e-16 };
+
+/// Terms below this fraction of the sum end a Taylor series
+const SERIES_EPSILON: f64 = 1e-33;
+
+/// Exact sum: `a + b = s + e`
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    let bb = s - a;
+    (s, (a - (s - bb)) + (b - bb))
+}
+
+/// Exact sum when |a| >= |b|
+fn quick_two_sum(a: f64, b: f64) -> DoubleDouble {
+    let s = a + b;
+    DoubleDouble { hi: s, lo: b - (s - a) }
+}
+
+/// Exact product: `a * b = p + e`
+#[cfg(target_feature = "fma")]
+fn two_prod(a: f64, b: f64) -> (f64, f64) {
+    let p = a * b;
+    (p, a.mul_add(b, -p))
+}
+
+/// Exact product: `a * b = p + e`, by Dekker's algorithm where `mul_add` would be
+/// a slow software call
+#[cfg(not(target_feature = "fma"))]
+fn two_prod(a: f64, b: f64) -> (f64, f64) {
+    let p = a * b;
+    let (a_hi, a_lo) = split(a);
+    let (b_hi, b_lo) = split(b);
+    (p, ((a_hi * b_hi - p) + a_hi * b_lo + a_lo * b_hi) + a_lo * b_lo)
+}
+
+/// `a = hi + lo` with both halves fitting in 26 bits, so their products are exact
+#[cfg(not(target_feature = "fma"))]
+fn split(a: f64) -> (f64, f64) {
+    const SPLITTER: f64 = 134_217_729.0; // 2^27 + 1
+    let t = SPLITTER * a;
+    let hi = t - (t - a);
+    (hi, a - hi)
+}
+
+impl DoubleDouble {
+    pub const fn new(hi: f64) -> DoubleDouble {
+        DoubleDouble { hi, lo: 0.0 }
+    }
+
+    /// Multiply by 2^n, exactly
+    fn scale(self, n: i32) -> DoubleDouble {
+        let k = 2f64.powi(n);
+        DoubleDouble { hi: self.hi * k, lo: self.lo * k }
+    }
+
+    fn mul_f64(self, k: f64) -> DoubleDouble {
+        let (p, e) = two_prod(self.hi, k);
+        quick_two_sum(p, e + self.lo * k)
+    }
+
+    /// Sum of the Taylor series terms x^first / first!, x^(first + 2) / (first + 2)!, ...
+    /// (`step` 2) or of every power (`step` 1), `sign` alternating them when -1
+    fn series(x: DoubleDouble, first: u32, step: u32, sign: f64) -> DoubleDouble {
+        let mut term = DoubleDouble::ONE;
+        for k in 1..=first {
+            term = term * x / DoubleDouble::new(k as f64);
+        }
+        let x_step = if step == 2 { x * x } else { x };
+
+        let mut sum = term;
+        let mut k = first;
+        loop {
+            let mut next = term * x_step;
+            for _ in 0..step {
+                k += 1;
+                next = next / DoubleDouble::new(k as f64);
+            }
+            term = next.mul_f64(sign);
+            sum = sum + term;
+            if term.hi.abs() <= SERIES_EPSILON * sum.hi.abs() || k > 200 {
+                return sum;
+            }
+        }
+    }
+}
+
+impl From<f64> for DoubleDouble {
+    fn from(v: f64) -> DoubleDouble {
+        DoubleDouble::new(v)
+    }
+}
+
+impl Add for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn add(self, other: DoubleDouble) -> DoubleDouble {
+        let (s, e) = two_sum(self.hi, other.hi);
+        let (t, f) = two_sum(self.lo, other.lo);
+        let r = quick_two_sum(s, e + t);
+        quick_two_sum(r.hi, r.lo + f)
+    }
+}
+
+impl Sub for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn sub(self, other: DoubleDouble) -> DoubleDouble {
+        self + -other
+    }
+}
+
+impl Mul for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn mul(self, other: DoubleDouble) -> DoubleDouble {
+        let (p, e) = two_prod(self.hi, other.hi);
+        quick_two_sum(p, e + (self.hi * other.lo + self.lo * other.hi))
+    }
+}
+
+impl Div for DoubleDouble {
+    type Output = DoubleDouble;
+
+    /// Long division, one `f64` quotient digit at a time
+    fn div(self, other: DoubleDouble) -> DoubleDouble {
+        let q1 = self.hi / other.hi;
+        let r = self - other.mul_f64(q1);
+        let q2 = r.hi / other.hi;
+        let r = r - other.mul_f64(q2);
+        let q3 = r.hi / other.hi;
+        quick_two_sum(q1, q2) + DoubleDouble::new(q3)
+    }
+}
+
+impl Neg for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn neg(self) -> DoubleDouble {
+        DoubleDouble { hi: -self.hi, lo: -self.lo }
+    }
+}
+
+impl PartialOrd for DoubleDouble {
+    fn partial_cmp(&self, other: &DoubleDouble) -> Option<Ordering> {
+        match self.hi.partial_cmp(&other.hi)? {
+            Ordering::Equal => self.lo.partial_cmp(&other.lo),
+            ordering => Some(ordering),
+        }
+    }
+}
+
+impl Real for DoubleDouble {
+    const ZERO: DoubleDouble = DoubleDouble::new(0.0);
+    const ONE: DoubleDouble = DoubleDouble::new(1.0);
+
+    fn from_f64(v: f64) -> DoubleDouble {
+        DoubleDouble::new(v)
+    }
+
+    fn to_f64(self) -> f64 {
+        self.hi + self.lo
+    }
+
+    fn abs(self) -> DoubleDouble {
+        if self.hi < 0.0 { -self } else { self }
+    }
+
+    fn copysign(self, sign: DoubleDouble) -> DoubleDouble {
+        if self.hi.is_sign_negative() == sign.hi.is_sign_negative() { self } else { -self }
+    }
+
+    /// One Newton step from the `f64` root doubles its precision
+    fn sqrt(self) -> DoubleDouble {
+        if self.hi <= 0.0 {
+            return DoubleDouble::new(self.hi.sqrt());
+        }
+        let x = DoubleDouble::new(self.hi.sqrt());
+        x + (self - x * x).mul_f64(0.5 / x.hi)
+    }
+
+    /// exp(x) = 2^k exp(r)^1024 with r = (x - k ln 2) / 1024 small enough for a short series
+    fn exp(self) -> DoubleDouble {
+        if !self.hi.is_finite() || self.hi.abs() > 709.0 {
+            return DoubleDouble::new(self.hi.exp());
+        }
+        let k = (self.hi / LN_2.hi).round();
+        let r = (self - LN_2.mul_f64(k)).scale(-10);
+
+        let mut e = DoubleDouble::series(r, 0, 1, 1.0);
+        for _ in 0..10 {
+            e = e * e;
+        }
+        e.scale(k as i32)
+    }
+
+    /// One Newton step y + x exp(-y) - 1 from the `f64` logarithm
+    fn ln(self) -> DoubleDouble {
+        if self.hi <= 0.0 || !self.hi.is_finite() {
+            return DoubleDouble::new(self.hi.ln());
+        }
+        let y = DoubleDouble::new(self.hi.ln());
+        y + self * (-y).exp() - DoubleDouble::ONE
+    }
+
+    fn sin(self) -> DoubleDouble {
+        self.sin_cos().0
+    }
+
+    fn cos(self) -> DoubleDouble {
+        self.sin_cos().1
+    }
+
+    /// Taylor series after reducing the argument to [-pi, pi]
+    fn sin_cos(self) -> (DoubleDouble, DoubleDouble) {
+        if !self.hi.is_finite() {
+            return (DoubleDouble::new(f64::NAN), DoubleDouble::new(f64::NAN));
+        }
+        let n = (self.hi / TAU.hi).round();
+        let r = self - TAU.mul_f64(n);
+        (DoubleDouble::series(r, 1, 2, -1.0), DoubleDouble::series(r, 0, 2, -1.0))
+    }
+
+    /// One Newton step on tan(theta) = y / x from the `f64` angle
+    fn atan2(self, x: DoubleDouble) -> DoubleDouble {
+        let theta = DoubleDouble::new(self.hi.atan2(x.hi));
+        let (sin, cos) = theta.sin_cos();
+        let denominator = x * cos + self * sin;
+        if denominator.hi == 0.0 {
+            return theta;
+        }
+        theta + (self * cos - x * sin) / denominator
+    }
+}
+
+impl std::fmt::Display for DoubleDouble {
+    /// Scientific notation with 32 significant digits, trailing zeros removed
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.hi == 0.0 || !self.hi.is_finite() {
+            return write!(f, "{}", self.hi);
+        }
+        let sign = if self.hi < 0.0 { "-" } else { "" };
+        let mut x = self.abs();
+
+        // Bring x into [1, 10)
+        let mut exp10 = x.hi.log10().floor() as i32;
+        let ten = DoubleDouble::new(10.0);
+        let power = (0..exp10.unsigned_abs()).fold(DoubleDouble::ONE, |p, _| p * ten);
+        x = if exp10 >= 0 { x / power } else { x * power };
+        if x.hi >= 10.0 {
+            x = x / ten;
+            exp10 += 1;
+        } else if x.hi < 1.0 {
+            x = x * ten;
+            exp10 -= 1;
+        }
+
+        let mut digits = Vec::with_capacity(33);
+        for _ in 0..33 {
+            let d = x.hi.floor().clamp(0.0, 9.0);
+            digits.push(d as u8);
+            x = (x - DoubleDouble::new(d)) * ten;
+        }
+
+        // Round to 32 digits
+        if digits.pop() >= Some(5) {
+            for i in (0..digits.len()).rev() {
+                digits[i] += 1;
+                if digits[i] < 10 {
+                    break;
+                }
+                digits[i] = 0;
+                if i == 0 {
+                    digits.insert(0, 1);
+                    digits.pop();
+                    exp10 += 1;
+                }
+            }
+        }
+
+        let text: String = digits.iter().map(|d| char::from(b'0' + d)).collect();
+        let (int_part, frac_part) = text.split_at(1);
+        let frac_part = frac_part.trim_end_matches('0');
+        if frac_part.is_empty() {
+            write!(f, "{sign}{int_part}e{exp10}")
+        } else {
+            write!(f, "{sign}{int_part}.{frac_part}e{exp10}")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Constants to double-double precision
+    const E: DoubleDouble = DoubleDouble { hi: std::f64::consts::E, lo: 1.4456468917292502e-16 };
+    const PI: DoubleDouble = DoubleDouble { hi: std::f64::consts::PI, lo: 1.2246467991473532e-16 };
+    const SQRT_2: DoubleDouble = DoubleDouble { hi: std::f64::consts::SQRT_2, lo: -9.667293313452913e-17 };
+
+    fn assert_close(actual: DoubleDouble, expected: DoubleDouble, tolerance: f64) {
+        let error = (actual - expected).abs().hi / expected.abs().hi.max(1.0);
+        assert!(error <= tolerance, "{actual} differs from {expected} by {error:e}");
+    }
+
+    #[test]
+    fn two_sum_keeps_the_rounding_error() {
+        assert_eq!(two_sum(1.0, 1e-20), (1.0, 1e-20));
+        let (s, e) = two_sum(0.1, 0.2);
+        assert_eq!(s, 0.1 + 0.2);
+        assert_eq!(e, -2.7755575615628914e-17);
+    }
+
+    #[test]
+    fn two_prod_keeps_the_rounding_error() {
+        // (1 + 2^-30)^2 = 1 + 2^-29 + 2^-60, whose last term f64 drops
+        let a = 1.0 + 2f64.powi(-30);
+        assert_eq!(two_prod(a, a), (1.0 + 2f64.powi(-29), 2f64.powi(-60)));
+    }
+
+    #[test]
+    fn arithmetic_beyond_f64() {
+        let one = DoubleDouble::ONE;
+        let tiny = DoubleDouble::new(1e-20);
+        assert_eq!((one + tiny - one).to_f64(), 1e-20);
+
+        let third = one / DoubleDouble::new(3.0);
+        assert_close(third * DoubleDouble::new(3.0), one, 1e-31);
+        assert_eq!(third.to_string(), "3.3333333333333333333333333333333e-1");
+        assert_close(SQRT_2 * SQRT_2, DoubleDouble::new(2.0), 1e-31);
+    }
+
+    #[test]
+    fn functions_reach_double_double_precision() {
+        let one = DoubleDouble::ONE;
+        assert_close(DoubleDouble::new(2.0).sqrt(), SQRT_2, 1e-31);
+        // The ten squarings in exp, which ln uses too, amplify its rounding error about a thousandfold
+        assert_close(one.exp(), E, 1e-29);
+        assert_close(E.ln(), one, 1e-29);
+        assert_close(DoubleDouble::new(2.0).ln(), LN_2, 1e-29);
+        assert_close(PI.sin(), DoubleDouble::ZERO, 1e-31);
+        assert_close(PI.cos(), -one, 1e-31);
+        assert_close(one.atan2(one).mul_f64(4.0), PI, 1e-31);
+        assert_close(DoubleDouble::new(2.0).powf(DoubleDouble::new(0.5)), SQRT_2, 1e-29);
+    }
+
+    #[test]
+    fn functions_agree_with_f64() {
+        for x in [0.001, 0.3, 1.0, 2.5, 17.0, 123.456] {
+            let dd = DoubleDouble::new(x);
+            let close = |actual: DoubleDouble, expected: f64| {
+                let error = (actual.to_f64() - expected).abs() / expected.abs().max(1.0);
+                assert!(error <= 4.0 * f64::EPSILON, "{actual} against {expected} for {x}");
+            };
+            close(dd.sqrt(), x.sqrt());
+            close(dd.exp(), x.exp());
+            close(dd.ln(), x.ln());
+            close(dd.sin(), x.sin());
+            close(dd.cos(), x.cos());
+            close((-dd).sin(), (-x).sin());
+            close(dd.atan2(DoubleDouble::new(-1.5)), x.atan2(-1.5));
+            close(dd.powf(DoubleDouble::new(2.7)), x.powf(2.7));
+        }
+    }
+}
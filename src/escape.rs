@@ -0,0 +1,288 @@
+use crate::complex::Complex;
+use crate::formula::{FractalFormula, Mandelbrot};
+use crate::real::Real;
+use crate::trap::{OrbitTrap, TrapHit, TrapTracker};
+
+/// Two orbit values closer than this are considered the same point of a cycle
+const PERIOD_EPSILON: f64 = 1e-14;
+
+/// Compute the escape time for a point in the Mandelbrot set, in the float type of `c`.
+pub fn mandelbrot<T: Real>(c: Complex<T>, max_iter: usize) -> Option<f64> {
+    iterate_parameter(&Mandelbrot, c, max_iter).escape_time
+}
+
+/// Compute the escape time for a point in the Julia set of the constant `c`.
+pub fn julia_set<T: Real>(z0: Complex<T>, c: Complex<T>, max_iter: usize) -> Option<f64> {
+    escape_time(&Mandelbrot, z0, c, max_iter)
+}
+
+/// Compute the smooth escape time of the orbit of `z0` under `formula`.
+pub fn escape_time<T: Real, F: FractalFormula<T> + ?Sized>(
+    formula: &F,
+    z0: Complex<T>,
+    c: Complex<T>,
+    max_iter: usize,
+) -> Option<f64> {
+    iterate(formula, z0, c, max_iter).escape_time
+}
+
+/// Raw result of iterating one point
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    /// Smooth escape time, `None` if the orbit stayed bounded
+    pub escape_time: Option<f64>,
+    /// Last value of the orbit: the escaping z, the last z before `max_iter`,
+    /// or a point of the cycle the orbit was caught in
+    pub z: Complex,
+    /// Period of the attracting cycle, when one was detected
+    pub period: Option<usize>,
+    /// Distance estimate to the boundary of the set in the complex plane: from
+    /// outside for escaping points, when requested and the formula has a
+    /// derivative, and from inside for parameters caught in a cycle
+    pub distance: Option<f64>,
+    /// Multiplier of the attracting cycle, when one was detected and the formula
+    /// has a derivative
+    pub multiplier: Option<Complex>,
+    /// What the orbit left in the orbit trap, when one is set
+    pub trap: Option<TrapHit>,
+}
+
+/// Iterate an image point: the parameter c of the formula, or z_0 when a Julia
+/// constant is given. `distance` enables the exterior distance estimate and
+/// `trap` follows the orbit through an orbit trap.
+pub fn iterate_point<T: Real, F: FractalFormula<T> + ?Sized>(
+    formula: &F,
+    point: Complex<T>,
+    julia: Option<Complex<T>>,
+    max_iter: usize,
+    distance: bool,
+    trap: Option<&OrbitTrap>,
+) -> Sample {
+    let (mut sample, c) = match julia {
+        // Known interior points skip their orbit, which the trap needs
+        None => {
+            let sample = trap.is_none().then(|| formula.known_interior(point)).flatten().unwrap_or_else(|| {
+                let z0 = if formula.starts_at_c() { point } else { Complex::ZERO };
+                orbit(formula, z0, point, point, max_iter, distance.then_some(Plane::Parameter), trap)
+            });
+            (sample, point)
+        }
+        Some(c) => (orbit(formula, point, c, point, max_iter, distance.then_some(Plane::Dynamic), trap), c),
+    };
+
+    if let Some((multiplier, interior_distance)) = sample.period.and_then(|p| cycle_estimates(formula, sample.z, c, p)) {
+        sample.multiplier = Some(multiplier);
+        // The estimate is a distance between parameters, meaningless for a Julia set
+        if julia.is_none() {
+            sample.distance = Some(interior_distance);
+        }
+    }
+    sample
+}
+
+/// Multiplier of the cycle of `period` through `z`, and the interior distance
+/// estimate (1 - |dz|^2) / |dc dz + dz dz dc / (1 - dz)| of its parameter `c`,
+/// for formulas of the form g(z) + c
+/// https://en.wikipedia.org/wiki/Plotting_algorithms_for_the_Mandelbrot_set#Interior_distance_estimation
+fn cycle_estimates<T: Real, F: FractalFormula<T> + ?Sized>(
+    formula: &F,
+    z: Complex,
+    c: Complex<T>,
+    period: usize,
+) -> Option<(Complex, f64)> {
+    let one = Complex::ONE;
+    let mut z = z.cast();
+    // Derivatives of the period-fold map with respect to z, c, z twice, and c then z
+    let (mut dz, mut dc, mut dzdz, mut dcdz) = (one, Complex::ZERO, Complex::ZERO, Complex::ZERO);
+
+    for _ in 0..period {
+        let (d1, d2) = (formula.derivative(z)?, formula.second_derivative(z)?);
+        dcdz = d2 * dc * dz + d1 * dcdz;
+        dzdz = d2 * dz.square() + d1 * dzdz;
+        dc = d1 * dc + one;
+        dz = d1 * dz;
+        z = formula.step(z, c);
+    }
+
+    let denominator = dcdz + dzdz * dc / (one - dz);
+    let distance = (T::ONE - dz.magnitude_squared()).to_f64() / denominator.magnitude().to_f64();
+    Some((dz.cast(), distance))
+}
+
+/// Iterate the orbit of z_0 = 0 for the parameter `c`, skipping the iteration
+/// when the formula knows `c` is interior.
+pub fn iterate_parameter<T: Real, F: FractalFormula<T> + ?Sized>(
+    formula: &F,
+    c: Complex<T>,
+    max_iter: usize,
+) -> Sample {
+    iterate_point(formula, c, None, max_iter, false, None)
+}
+
+/// Iterate the orbit of `z0` under `formula` and keep its escape time and last
+/// value. `z0` is also the pixel of formulas that read it.
+pub fn iterate<T: Real, F: FractalFormula<T> + ?Sized>(
+    formula: &F,
+    z0: Complex<T>,
+    c: Complex<T>,
+    max_iter: usize,
+) -> Sample {
+    orbit(formula, z0, c, z0, max_iter, None, None)
+}
+
+/// Variable the derivative of the orbit is taken with respect to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Plane {
+    /// dz/dc: z_0 = 0, or c when the formula starts there, and the pixel is c
+    Parameter,
+    /// dz/dz_0: the pixel is z_0
+    Dynamic,
+}
+
+/// Orbits are followed past the escape radius up to this radius so the
+/// distance estimate converges
+const DISTANCE_BAILOUT: f64 = 1e3;
+
+/// Upper bound on the extra iterations past the escape radius
+const DISTANCE_EXTRA_STEPS: usize = 16;
+
+/// Distance estimate |z| ln|z| / |dz| from an escaped orbit value and its derivative
+/// https://en.wikipedia.org/wiki/Plotting_algorithms_for_the_Mandelbrot_set#Distance_estimates
+pub fn distance_estimate<T: Real, F: FractalFormula<T> + ?Sized>(
+    formula: &F,
+    mut z: Complex<T>,
+    mut dz: Complex<T>,
+    c: Complex<T>,
+    plane: Plane,
+) -> Option<f64> {
+    for _ in 0..DISTANCE_EXTRA_STEPS {
+        if z.magnitude_squared().to_f64() > DISTANCE_BAILOUT * DISTANCE_BAILOUT {
+            break;
+        }
+        dz = next_derivative(formula, z, dz, plane)?;
+        z = formula.step(z, c);
+    }
+
+    // Maps that pull escaped orbits back inside the unit circle have no estimate
+    let r = z.magnitude().to_f64();
+    (r > 1.0).then(|| r * r.ln() / dz.magnitude().to_f64())
+}
+
+/// dz_{n+1} = f'(z_n) dz_n (+ 1 in the parameter plane)
+fn next_derivative<T: Real, F: FractalFormula<T> + ?Sized>(
+    formula: &F,
+    z: Complex<T>,
+    dz: Complex<T>,
+    plane: Plane,
+) -> Option<Complex<T>> {
+    let dz = formula.derivative(z)? * dz;
+    Some(match plane {
+        Plane::Parameter => dz + Complex::ONE,
+        Plane::Dynamic => dz,
+    })
+}
+
+/// Iterate the orbit of `z0` for the image point `pixel`, tracking its
+/// derivative for the given plane if any.
+///
+/// Bounded orbits are cut short as soon as they are caught in a cycle, found with
+/// Brent's algorithm: z is compared to a saved value that is refreshed at every
+/// power of two iterations. By then the whole cycle has been through the trap.
+fn orbit<T: Real, F: FractalFormula<T> + ?Sized>(
+    formula: &F,
+    z0: Complex<T>,
+    c: Complex<T>,
+    pixel: Complex<T>,
+    max_iter: usize,
+    plane: Option<Plane>,
+    trap: Option<&OrbitTrap>,
+) -> Sample {
+    let mut tracker = trap.map(TrapTracker::new);
+    let mut z = z0;
+    let mut saved: Complex = z0.cast();
+    let mut window = 1;
+    let mut steps = 0;
+
+    // Derivative of z_0: 0 with respect to c unless z_0 is c, 1 with respect to z_0
+    let mut dz = plane.map(|plane| match plane {
+        Plane::Parameter if formula.starts_at_c() => Complex::ONE,
+        Plane::Parameter => Complex::ZERO,
+        Plane::Dynamic => Complex::ONE,
+    });
+
+    // z_{n+1} = f(z_n, c), starting from z_0, checking z_0 .. z_{max_iter - 1}
+    for n in 0..max_iter {
+        if formula.escaped(z) {
+            // Apply smoothing formula if the point escaped
+            // https://en.wikipedia.org/wiki/Plotting_algorithms_for_the_Mandelbrot_set#Continuous_(smooth)_coloring
+            let zn = z.magnitude().to_f64();
+            // Custom bailouts can stop orbits with |z_n| <= 1, where the formula breaks down
+            let nu = if zn > 1.0 { (zn.ln()).ln() / formula.degree().ln() } else { 0.0 }; // ln(ln(|z_n|))/ln(d)
+            let escape_time = (n as f64) + 1.0 - nu; // Smooth iteration count
+
+            let distance = plane.zip(dz).and_then(|(plane, dz)| distance_estimate(formula, z, dz, c, plane));
+            let trap = tracker.and_then(TrapTracker::finish);
+            return Sample { escape_time: Some(escape_time), z: z.cast(), period: None, distance, multiplier: None, trap };
+        }
+        if let (true, Some(tracker)) = (n > 0, &mut tracker) {
+            tracker.visit(z.cast());
+        }
+        if n + 1 == max_iter {
+            break;
+        }
+
+        if let Some(plane) = plane {
+            dz = dz.and_then(|dz| next_derivative(formula, z, dz, plane));
+        }
+        z = formula.step_for_pixel(z, c, pixel);
+        steps += 1;
+
+        // Periodicity check, in `f64`: cycle points are far apart compared to its rounding
+        if (z.cast() - saved).magnitude_squared() < PERIOD_EPSILON * PERIOD_EPSILON {
+            let trap = tracker.and_then(TrapTracker::finish);
+            return Sample { escape_time: None, z: z.cast(), period: Some(steps), distance: None, multiplier: None, trap };
+        }
+        if steps == window {
+            saved = z.cast();
+            window *= 2;
+            steps = 0;
+        }
+    }
+
+    let trap = tracker.and_then(TrapTracker::finish);
+    Sample { escape_time: None, z: z.cast(), period: None, distance: None, multiplier: None, trap }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formula::Fractal;
+
+    /// Samples of a 60 × 40 grid over [-2, 2] × [-1.5, 1.5]
+    fn grid(fractal: &Fractal, distance: bool) -> Vec<Sample> {
+        (0..60 * 40)
+            .map(|i| {
+                let c = Complex::new((i % 60) as f64 / 15.0 - 2.0, (i / 60) as f64 / 13.0 - 1.5);
+                iterate_point(fractal, c, None, 200, distance, None)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn negative_multibrot_powers_escape() {
+        for power in [-2.0, -3.0, -2.5] {
+            let samples = grid(&Fractal::Multibrot { power }, true);
+            assert!(samples.iter().all(|s| !s.z.re.is_nan() && !s.z.im.is_nan()), "power {power}");
+            let escaped: Vec<_> = samples.iter().filter(|s| s.escape_time.is_some()).collect();
+            assert!(escaped.len() > 100 && escaped.len() < samples.len() - 100, "power {power}");
+            assert!(escaped.iter().all(|s| s.distance.is_none_or(|d| d.is_finite() && d >= 0.0)), "power {power}");
+        }
+    }
+
+    #[test]
+    fn positive_powers_start_at_zero() {
+        // c = 0 stays at the fixed point 0 only when the orbit starts there
+        let sample = iterate_point(&Fractal::Multibrot { power: 3.0 }, Complex::<f64>::ZERO, None, 100, false, None);
+        assert_eq!((sample.escape_time, sample.z), (None, Complex::ZERO));
+    }
+}
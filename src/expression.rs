@@ -0,0 +1,785 @@
+//! A small expression language over complex numbers, for user-defined formulas
+//! such as `z^3 + c*sin(z)`.
+//!
+//! Expressions are parsed into a syntax tree, with constants folded, then
+//! compiled to bytecode for a small register machine that runs in any [`Real`]
+//! type. Variables and constants are read straight from the instructions, so
+//! `z^2 + c` takes two of them.
+//! Grammar, loosest binding first:
+//!
+//! ```text
+//! comparison := sum [("<" | ">" | "<=" | ">=") sum]
+//! sum        := product {("+" | "-") product}
+//! product    := unary {("*" | "/") unary}
+//! unary      := ("-" | "+") unary | power
+//! power      := atom ["^" unary]
+//! atom       := number | name | name "(" comparison ")" | "(" comparison ")" | "|" comparison "|"
+//! ```
+//!
+//! Names are the variables `z`, `c` and `pixel`, the constants `i`, `pi` and
+//! `e`, user parameters, and the functions listed in [`Function`]. `|x|` is the
+//! modulus; comparisons look at real parts and give 1 or 0. Expressions nest at
+//! most 100 levels deep, counting each chained `+`, `-`, `*` or `/` as a level.
+
+use crate::complex::Complex;
+use crate::real::Real;
+
+/// Most registers a program may use
+const MAX_REGISTERS: usize = 16;
+
+/// Most levels of brackets, unary operators and chained binary operators an
+/// expression may nest, which keeps the recursion over it off the end of the stack
+const MAX_DEPTH: usize = 100;
+
+/// Values an expression can read while it runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variable {
+    /// Current orbit value
+    Z,
+    /// Parameter of the formula: the pixel, or the Julia constant
+    C,
+    /// Point of the complex plane under the pixel
+    Pixel,
+}
+
+impl Variable {
+    fn name(self) -> &'static str {
+        match self {
+            Variable::Z => "z",
+            Variable::C => "c",
+            Variable::Pixel => "pixel",
+        }
+    }
+}
+
+/// Built-in functions of one argument
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Function {
+    Sin,
+    Cos,
+    Tan,
+    Sinh,
+    Cosh,
+    Exp,
+    /// Principal natural logarithm, also written `log`
+    Ln,
+    Sqrt,
+    Conj,
+    /// Modulus, as a real number
+    Abs,
+    /// Squared modulus, as a real number
+    Norm,
+    Re,
+    Im,
+    /// Argument in (-pi, pi], as a real number
+    Arg,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Function> {
+        Some(match name {
+            "sin" => Function::Sin,
+            "cos" => Function::Cos,
+            "tan" => Function::Tan,
+            "sinh" => Function::Sinh,
+            "cosh" => Function::Cosh,
+            "exp" => Function::Exp,
+            "ln" | "log" => Function::Ln,
+            "sqrt" => Function::Sqrt,
+            "conj" => Function::Conj,
+            "abs" => Function::Abs,
+            "norm" => Function::Norm,
+            "re" => Function::Re,
+            "im" => Function::Im,
+            "arg" => Function::Arg,
+            _ => return None,
+        })
+    }
+
+    fn apply<T: Real>(self, x: Complex<T>) -> Complex<T> {
+        let real = |v: T| Complex::new(v, T::ZERO);
+        match self {
+            Function::Sin => x.sin(),
+            Function::Cos => x.cos(),
+            Function::Tan => x.sin() / x.cos(),
+            // sinh x = -i sin(ix), cosh x = cos(ix)
+            Function::Sinh => -(Complex::I * (Complex::I * x).sin()),
+            Function::Cosh => (Complex::I * x).cos(),
+            Function::Exp => x.exp(),
+            Function::Ln => x.ln(),
+            Function::Sqrt => x.sqrt(),
+            Function::Conj => x.conj(),
+            Function::Abs => real(x.magnitude()),
+            Function::Norm => real(x.magnitude_squared()),
+            Function::Re => real(x.re),
+            Function::Im => real(x.im),
+            Function::Arg => real(x.arg()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+}
+
+impl BinaryOp {
+    fn apply<T: Real>(self, a: Complex<T>, b: Complex<T>) -> Complex<T> {
+        match self {
+            BinaryOp::Add => a + b,
+            BinaryOp::Sub => a - b,
+            BinaryOp::Mul => a * b,
+            BinaryOp::Div => a / b,
+            BinaryOp::Pow => a.powc(b),
+            BinaryOp::Less => truth(a.re < b.re),
+            BinaryOp::Greater => truth(a.re > b.re),
+            BinaryOp::LessEqual => truth(a.re <= b.re),
+            BinaryOp::GreaterEqual => truth(a.re >= b.re),
+        }
+    }
+
+    fn is_comparison(self) -> bool {
+        matches!(self, BinaryOp::Less | BinaryOp::Greater | BinaryOp::LessEqual | BinaryOp::GreaterEqual)
+    }
+}
+
+/// 1 for true, 0 for false
+fn truth<T: Real>(v: bool) -> Complex<T> {
+    if v { Complex::ONE } else { Complex::ZERO }
+}
+
+/// Syntax tree, with every constant subexpression already evaluated
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Constant(Complex),
+    Variable(Variable),
+    Neg(Box<Node>),
+    Binary(BinaryOp, Box<Node>, Box<Node>),
+    Call(Function, Box<Node>),
+}
+
+impl Node {
+    fn neg(x: Node) -> Node {
+        match x {
+            Node::Constant(v) => Node::Constant(-v),
+            x => Node::Neg(Box::new(x)),
+        }
+    }
+
+    fn binary(op: BinaryOp, a: Node, b: Node) -> Node {
+        match (a, b) {
+            (Node::Constant(a), Node::Constant(b)) => Node::Constant(op.apply(a, b)),
+            // Compare squared moduli instead, without the square root: |z| > 2 is norm(z) > 4
+            (Node::Call(Function::Abs, x), Node::Constant(r)) if op.is_comparison() && r.im == 0.0 && r.re >= 0.0 => {
+                Node::Binary(op, Box::new(Node::Call(Function::Norm, x)), Box::new(Node::Constant(r.square())))
+            }
+            (Node::Constant(r), Node::Call(Function::Abs, x)) if op.is_comparison() && r.im == 0.0 && r.re >= 0.0 => {
+                Node::Binary(op, Box::new(Node::Constant(r.square())), Box::new(Node::Call(Function::Norm, x)))
+            }
+            (a, b) => Node::Binary(op, Box::new(a), Box::new(b)),
+        }
+    }
+
+    fn call(function: Function, x: Node) -> Node {
+        match x {
+            Node::Constant(v) => Node::Constant(function.apply(v)),
+            x => Node::Call(function, Box::new(x)),
+        }
+    }
+
+    /// Growth degree in z, `None` when the expression does not grow like a power of z
+    fn degree(&self) -> Option<f64> {
+        match self {
+            Node::Constant(_) | Node::Variable(Variable::C | Variable::Pixel) => Some(0.0),
+            Node::Variable(Variable::Z) => Some(1.0),
+            Node::Neg(x) => x.degree(),
+            Node::Binary(op, a, b) => {
+                let (a_deg, b_deg) = (a.degree()?, b.degree()?);
+                match op {
+                    BinaryOp::Add | BinaryOp::Sub => Some(a_deg.max(b_deg)),
+                    BinaryOp::Mul => Some(a_deg + b_deg),
+                    BinaryOp::Div => Some(a_deg - b_deg),
+                    BinaryOp::Pow => match **b {
+                        Node::Constant(p) if p.im == 0.0 => Some(a_deg * p.re),
+                        _ if a_deg == 0.0 && b_deg == 0.0 => Some(0.0),
+                        _ => None,
+                    },
+                    _ => None,
+                }
+            }
+            Node::Call(function, x) => {
+                let x_deg = x.degree()?;
+                match function {
+                    Function::Conj | Function::Abs | Function::Re | Function::Im => Some(x_deg),
+                    Function::Norm => Some(x_deg * 2.0),
+                    Function::Sqrt => Some(x_deg / 2.0),
+                    _ if x_deg == 0.0 => Some(0.0),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+/// Where an instruction reads a value from
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operand {
+    Constant(Complex),
+    Variable(Variable),
+    /// Result of an earlier instruction
+    Register(usize),
+}
+
+/// Operation of an instruction
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OpKind {
+    Neg,
+    Square,
+    /// Raise to a constant integer power
+    PowI(i32),
+    /// Raise to a constant real power
+    PowF(f64),
+    Call(Function),
+    Binary(BinaryOp),
+}
+
+/// Apply `kind` to `a` (and `b` for binary operations) and store the result in register `target`
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Instruction {
+    kind: OpKind,
+    target: usize,
+    a: Operand,
+    b: Operand,
+}
+
+/// A compiled expression
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    code: Vec<Instruction>,
+    /// Where the value of the expression is found once the code has run
+    result: Operand,
+    /// Number of registers the code uses
+    registers: usize,
+    /// Growth degree in z, when the expression grows like a power of z
+    degree: Option<f64>,
+    /// Whether the result is a comparison, 1 for true and 0 for false
+    is_condition: bool,
+}
+
+impl Program {
+    /// Parse and compile `source`, which may read the `variables` and the named
+    /// constants in `params`; errors give the column of the problem and show it
+    pub fn compile(source: &str, variables: &[Variable], params: &[(String, Complex)]) -> Result<Program, String> {
+        let mut parser = Parser { source, tokens: tokenize(source)?, pos: 0, depth: 0, variables, params };
+        let node = parser.comparison()?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(error_at(source, token.column, "expected an operator"));
+        }
+
+        let mut code = Vec::new();
+        let result = emit(&node, 0, &mut code);
+        let registers = code.iter().map(|instruction| instruction.target + 1).max().unwrap_or(0);
+        if registers > MAX_REGISTERS {
+            return Err(error_at(source, 1, "expression is too deeply nested"));
+        }
+        let is_condition = matches!(node, Node::Binary(op, ..) if op.is_comparison());
+        Ok(Program { code, result, registers, degree: node.degree(), is_condition })
+    }
+
+    /// Growth degree in z, when the expression grows like a power of z
+    pub fn degree(&self) -> Option<f64> {
+        self.degree
+    }
+
+    /// Whether the whole expression is a comparison
+    pub fn is_condition(&self) -> bool {
+        self.is_condition
+    }
+
+    /// r^2 when the program is the condition |z| > r, which callers can test
+    /// without running it
+    pub fn escape_radius_squared(&self) -> Option<f64> {
+        match self.code[..] {
+            [
+                Instruction { kind: OpKind::Call(Function::Norm), target: 0, a: Operand::Variable(Variable::Z), .. },
+                Instruction {
+                    kind: OpKind::Binary(BinaryOp::Greater),
+                    target: 0,
+                    a: Operand::Register(0),
+                    b: Operand::Constant(r2),
+                },
+            ] if r2.im == 0.0 => Some(r2.re),
+            _ => None,
+        }
+    }
+
+    /// Run the program for the given variable values
+    pub fn eval<T: Real>(&self, z: Complex<T>, c: Complex<T>, pixel: Complex<T>) -> Complex<T> {
+        // Clearing the register file is a good part of the cost of small programs
+        match self.registers {
+            0..=2 => self.run::<T, 2>(z, c, pixel),
+            3..=4 => self.run::<T, 4>(z, c, pixel),
+            _ => self.run::<T, MAX_REGISTERS>(z, c, pixel),
+        }
+    }
+
+    /// `eval` with `N` registers, a power of two so register numbers are masked
+    /// instead of bounds checked
+    fn run<T: Real, const N: usize>(&self, z: Complex<T>, c: Complex<T>, pixel: Complex<T>) -> Complex<T> {
+        let mut registers = [Complex::<T>::ZERO; N];
+        let read = |registers: &[Complex<T>; N], operand| match operand {
+            Operand::Constant(v) => v.cast(),
+            Operand::Variable(Variable::Z) => z,
+            Operand::Variable(Variable::C) => c,
+            Operand::Variable(Variable::Pixel) => pixel,
+            Operand::Register(r) => registers[r % N],
+        };
+
+        for instruction in &self.code {
+            let a = read(&registers, instruction.a);
+            registers[instruction.target % N] = match instruction.kind {
+                OpKind::Neg => -a,
+                OpKind::Square => a.square(),
+                OpKind::PowI(n) => a.powi(n),
+                OpKind::PowF(p) => a.powf(T::from_f64(p)),
+                // The cheap operations are inlined here, the others are calls
+                OpKind::Call(Function::Norm) => Complex::new(a.magnitude_squared(), T::ZERO),
+                OpKind::Call(function) => function.apply(a),
+                OpKind::Binary(BinaryOp::Add) => a + read(&registers, instruction.b),
+                OpKind::Binary(BinaryOp::Sub) => a - read(&registers, instruction.b),
+                OpKind::Binary(BinaryOp::Mul) => a * read(&registers, instruction.b),
+                OpKind::Binary(op) => op.apply(a, read(&registers, instruction.b)),
+            };
+        }
+        read(&registers, self.result)
+    }
+}
+
+/// Append the code of `node` to `code`, using registers from `target` up;
+/// returns where its value is found
+fn emit(node: &Node, target: usize, code: &mut Vec<Instruction>) -> Operand {
+    let (kind, a, b) = match node {
+        Node::Constant(v) => return Operand::Constant(*v),
+        Node::Variable(variable) => return Operand::Variable(*variable),
+        Node::Neg(x) => {
+            let a = emit(x, target, code);
+            (OpKind::Neg, a, a)
+        }
+        Node::Call(function, x) => {
+            let a = emit(x, target, code);
+            (OpKind::Call(*function), a, a)
+        }
+        // Constant real powers avoid the complex logarithm
+        Node::Binary(BinaryOp::Pow, base, exponent) if let Node::Constant(p) = **exponent && p.im == 0.0 => {
+            let a = emit(base, target, code);
+            let kind = if p.re == 2.0 {
+                OpKind::Square
+            } else if p.re.fract() == 0.0 && p.re.abs() <= i32::MAX as f64 {
+                OpKind::PowI(p.re as i32)
+            } else {
+                OpKind::PowF(p.re)
+            };
+            (kind, a, a)
+        }
+        // The second operand goes one register up so it keeps the first
+        Node::Binary(op, a, b) => (OpKind::Binary(*op), emit(a, target, code), emit(b, target + 1, code)),
+    };
+    code.push(Instruction { kind, target, a, b });
+    Operand::Register(target)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Number(f64),
+    Name(String),
+    /// Operator or bracket
+    Symbol(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    kind: TokenKind,
+    /// 1-based column of the first character
+    column: usize,
+}
+
+const SYMBOLS: [&str; 13] = ["<=", ">=", "<", ">", "+", "-", "*", "/", "^", "(", ")", "|", ","];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let column = i + 1;
+        let ch = chars[i];
+        if ch.is_whitespace() {
+            i += 1;
+        } else if ch.is_ascii_digit() || ch == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            // Exponent, only when digits follow so that "2e" is not swallowed
+            if i < chars.len() && matches!(chars[i], 'e' | 'E') {
+                let mut j = i + 1;
+                if j < chars.len() && matches!(chars[j], '+' | '-') {
+                    j += 1;
+                }
+                if j < chars.len() && chars[j].is_ascii_digit() {
+                    i = j;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            let text: String = chars[start..i].iter().collect();
+            let value = text.parse().map_err(|_| error_at(source, column, &format!("invalid number \"{text}\"")))?;
+            tokens.push(Token { kind: TokenKind::Number(value), column });
+        } else if ch.is_alphabetic() || ch == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token { kind: TokenKind::Name(chars[start..i].iter().collect()), column });
+        } else {
+            let rest: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+            let Some(symbol) = SYMBOLS.iter().find(|s| rest.starts_with(*s)) else {
+                return Err(error_at(source, column, &format!("unexpected character '{ch}'")));
+            };
+            i += symbol.len();
+            tokens.push(Token { kind: TokenKind::Symbol(symbol), column });
+        }
+    }
+    Ok(tokens)
+}
+
+/// "column N: message" followed by the source with a caret under column N
+fn error_at(source: &str, column: usize, message: &str) -> String {
+    format!("column {column}: {message}\n  {source}\n  {:>column$}", "^")
+}
+
+/// Recursive descent parser, one method per grammar rule
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Nesting of the node being parsed, up to `MAX_DEPTH`
+    depth: usize,
+    variables: &'a [Variable],
+    params: &'a [(String, Complex)],
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.pos).map(|t| &t.kind)
+    }
+
+    /// Column of the next token, or just past the end
+    fn column(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.source.chars().count() + 1, |t| t.column)
+    }
+
+    fn error(&self, column: usize, message: &str) -> String {
+        error_at(self.source, column, message)
+    }
+
+    /// Consume the next token if it is `symbol`
+    fn eat(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Some(TokenKind::Symbol(s)) if *s == symbol) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), String> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            Err(self.error(self.column(), &format!("expected \"{symbol}\"")))
+        }
+    }
+
+    /// Go one level deeper, failing past `MAX_DEPTH`
+    fn nest(&mut self) -> Result<(), String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(self.error(self.column(), "expression is too deeply nested"));
+        }
+        Ok(())
+    }
+
+    fn comparison(&mut self) -> Result<Node, String> {
+        let a = self.sum()?;
+        let op = match self.peek() {
+            Some(TokenKind::Symbol("<")) => BinaryOp::Less,
+            Some(TokenKind::Symbol(">")) => BinaryOp::Greater,
+            Some(TokenKind::Symbol("<=")) => BinaryOp::LessEqual,
+            Some(TokenKind::Symbol(">=")) => BinaryOp::GreaterEqual,
+            _ => return Ok(a),
+        };
+        self.pos += 1;
+        let b = self.sum()?;
+        Ok(Node::binary(op, a, b))
+    }
+
+    fn sum(&mut self) -> Result<Node, String> {
+        let depth = self.depth;
+        let mut node = self.product()?;
+        loop {
+            let op = match self.peek() {
+                Some(TokenKind::Symbol("+")) => BinaryOp::Add,
+                Some(TokenKind::Symbol("-")) => BinaryOp::Sub,
+                _ => {
+                    self.depth = depth;
+                    return Ok(node);
+                }
+            };
+            // Each operator puts the terms before it one level deeper
+            self.nest()?;
+            self.pos += 1;
+            node = Node::binary(op, node, self.product()?);
+        }
+    }
+
+    fn product(&mut self) -> Result<Node, String> {
+        let depth = self.depth;
+        let mut node = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(TokenKind::Symbol("*")) => BinaryOp::Mul,
+                Some(TokenKind::Symbol("/")) => BinaryOp::Div,
+                _ => {
+                    self.depth = depth;
+                    return Ok(node);
+                }
+            };
+            self.nest()?;
+            self.pos += 1;
+            node = Node::binary(op, node, self.unary()?);
+        }
+    }
+
+    /// Every recursion goes through here, so it is where nesting is counted
+    fn unary(&mut self) -> Result<Node, String> {
+        self.nest()?;
+        let node = if self.eat("-") {
+            Node::neg(self.unary()?)
+        } else if self.eat("+") {
+            self.unary()?
+        } else {
+            self.power()?
+        };
+        self.depth -= 1;
+        Ok(node)
+    }
+
+    fn power(&mut self) -> Result<Node, String> {
+        let base = self.atom()?;
+        if self.eat("^") {
+            // Right associative, and -z^2 is -(z^2) while z^-2 is z^(-2)
+            Ok(Node::binary(BinaryOp::Pow, base, self.unary()?))
+        } else {
+            Ok(base)
+        }
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        let column = self.column();
+        let Some(token) = self.tokens.get(self.pos).cloned() else {
+            return Err(self.error(column, "unexpected end of expression"));
+        };
+        self.pos += 1;
+
+        match token.kind {
+            TokenKind::Number(v) => Ok(Node::Constant(Complex::new(v, 0.0))),
+            TokenKind::Symbol("(") => {
+                let node = self.comparison()?;
+                self.expect(")")?;
+                Ok(node)
+            }
+            TokenKind::Symbol("|") => {
+                let node = self.comparison()?;
+                self.expect("|")?;
+                Ok(Node::call(Function::Abs, node))
+            }
+            TokenKind::Symbol(symbol) => Err(self.error(column, &format!("unexpected \"{symbol}\""))),
+            TokenKind::Name(name) => {
+                if self.peek() == Some(&TokenKind::Symbol("(")) {
+                    let function = Function::from_name(&name)
+                        .ok_or_else(|| self.error(column, &format!("unknown function \"{name}\"")))?;
+                    self.pos += 1;
+                    let arg = self.comparison()?;
+                    if self.peek() == Some(&TokenKind::Symbol(",")) {
+                        return Err(self.error(self.column(), &format!("\"{name}\" takes one argument")));
+                    }
+                    self.expect(")")?;
+                    return Ok(Node::call(function, arg));
+                }
+                self.name(&name, column)
+            }
+        }
+    }
+
+    /// Variable, constant or parameter
+    fn name(&self, name: &str, column: usize) -> Result<Node, String> {
+        if let Some(variable) = [Variable::Z, Variable::C, Variable::Pixel].into_iter().find(|v| v.name() == name) {
+            return if self.variables.contains(&variable) {
+                Ok(Node::Variable(variable))
+            } else {
+                Err(self.error(column, &format!("\"{name}\" cannot be used here")))
+            };
+        }
+        if let Some((_, value)) = self.params.iter().find(|(p, _)| p == name) {
+            return Ok(Node::Constant(*value));
+        }
+        match name {
+            "i" => Ok(Node::Constant(Complex::I)),
+            "pi" => Ok(Node::Constant(Complex::new(std::f64::consts::PI, 0.0))),
+            "e" => Ok(Node::Constant(Complex::new(std::f64::consts::E, 0.0))),
+            _ if Function::from_name(name).is_some() => {
+                Err(self.error(column, &format!("function \"{name}\" needs an argument in parentheses")))
+            }
+            _ => Err(self.error(column, &format!("unknown name \"{name}\""))),
+        }
+    }
+}
+
+/// Whether `name` can be written as a name in an expression
+pub fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|ch| ch.is_alphabetic() || ch == '_') && chars.all(|ch| ch.is_alphanumeric() || ch == '_')
+}
+
+/// Whether `name` is taken by a variable, constant or function, so a parameter cannot use it
+pub fn is_reserved(name: &str) -> bool {
+    matches!(name, "z" | "c" | "pixel" | "i" | "pi" | "e") || Function::from_name(name).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::double::DoubleDouble;
+    use crate::formula::{FractalFormula, Mandelbrot};
+
+    fn compile(source: &str) -> Result<Program, String> {
+        Program::compile(source, &[Variable::Z, Variable::C], &[("k".to_string(), Complex::new(0.5, 0.0))])
+    }
+
+    fn eval(source: &str, z: Complex) -> Complex {
+        compile(source).unwrap().eval(z, Complex::new(0.25, -1.0), Complex::ZERO)
+    }
+
+    /// First line of the error of `source`
+    fn error(source: &str) -> String {
+        compile(source).unwrap_err().lines().next().unwrap().to_string()
+    }
+
+    #[test]
+    fn precedence() {
+        let z = Complex::new(3.0, 0.0);
+        assert_eq!(eval("1 + 2 * z", z), Complex::new(7.0, 0.0));
+        assert_eq!(eval("(1 + 2) * z", z), Complex::new(9.0, 0.0));
+        assert_eq!(eval("z - 1 - 1", z), Complex::new(1.0, 0.0));
+        assert_eq!(eval("z / 3 / 2", z), Complex::new(0.5, 0.0));
+        assert_eq!(eval("z ^ 1 ^ 2", z), Complex::new(3.0, 0.0));
+        assert_eq!(eval("-z ^ 2", z), Complex::new(-9.0, 0.0));
+        assert_eq!(eval("z ^ -1 * 3", z), Complex::new(1.0, 0.0));
+        assert_eq!(eval("k * z + c", z), Complex::new(1.75, -1.0));
+        assert_eq!(eval("1 + 2 < z", z), Complex::new(0.0, 0.0));
+        assert_eq!(eval("1 + 2 <= z", z), Complex::new(1.0, 0.0));
+    }
+
+    #[test]
+    fn modulus() {
+        let z = Complex::new(3.0, -4.0);
+        assert_eq!(eval("|z|", z), Complex::new(5.0, 0.0));
+        assert_eq!(eval("|z| + 1", z), Complex::new(6.0, 0.0));
+        assert_eq!(eval("| |z| - 6 |", z), Complex::new(1.0, 0.0));
+        assert_eq!(eval("|z| > 5", z), Complex::new(0.0, 0.0));
+        assert_eq!(eval("|z| >= 5", z), Complex::new(1.0, 0.0));
+        assert_eq!(eval("4.9 < |z|", z), Complex::new(1.0, 0.0));
+    }
+
+    #[test]
+    fn error_columns() {
+        assert_eq!(error("z + * c"), "column 5: unexpected \"*\"");
+        assert_eq!(error("z c"), "column 3: expected an operator");
+        assert_eq!(error("(z + c"), "column 7: expected \")\"");
+        assert_eq!(error("z + q"), "column 5: unknown name \"q\"");
+        assert_eq!(error("z + foo(z)"), "column 5: unknown function \"foo\"");
+        assert_eq!(error("sin z"), "column 1: function \"sin\" needs an argument in parentheses");
+        assert_eq!(error("sin(z, c)"), "column 6: \"sin\" takes one argument");
+        assert_eq!(error("z + pixel"), "column 5: \"pixel\" cannot be used here");
+        assert_eq!(error("z # c"), "column 3: unexpected character '#'");
+        assert_eq!(error("z^2 +"), "column 6: unexpected end of expression");
+        assert_eq!(compile("z + * c").unwrap_err(), "column 5: unexpected \"*\"\n  z + * c\n      ^");
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let nested = |n| format!("{}z{}", "(".repeat(n), ")".repeat(n));
+        assert!(compile(&nested(99)).is_ok());
+        assert_eq!(error(&nested(100)), "column 101: expression is too deeply nested");
+        assert_eq!(error(&nested(100_000)), "column 101: expression is too deeply nested");
+        assert!(compile(&format!("z{}", "+z".repeat(90))).is_ok());
+        assert_eq!(error(&format!("z{}", "+z".repeat(100_000))), "column 201: expression is too deeply nested");
+        assert!(error(&format!("{}z", "-".repeat(100_000))).ends_with("expression is too deeply nested"));
+    }
+
+    #[test]
+    fn register_limit() {
+        // Each level takes one more register for its right operand
+        let nested = |n| (0..n).fold("z".to_string(), |inner, _| format!("z * ({inner} + c)"));
+        assert!(compile(&nested(15)).is_ok());
+        assert_eq!(error(&nested(16)), "column 1: expression is too deeply nested");
+    }
+
+    #[test]
+    fn degree() {
+        let degree = |source| compile(source).unwrap().degree();
+        assert_eq!(degree("z^2 + c"), Some(2.0));
+        assert_eq!(degree("z^3 - z + c"), Some(3.0));
+        assert_eq!(degree("(z^2 + c)^2 + c"), Some(4.0));
+        assert_eq!(degree("exp(c) * z^2.5"), Some(2.5));
+        assert_eq!(degree("conj(z)^2 + c"), Some(2.0));
+        assert_eq!(degree("c / z^2"), Some(-2.0));
+        assert_eq!(degree("1 / z^2 + c"), Some(0.0));
+        assert_eq!(degree("sin(z) + c"), None);
+        assert_eq!(degree("z^c"), None);
+    }
+
+    #[test]
+    fn escape_radius_squared() {
+        let radius_squared = |source| compile(source).unwrap().escape_radius_squared();
+        assert_eq!(radius_squared("|z| > 2"), Some(4.0));
+        assert_eq!(radius_squared("|z| > 2 * 5"), Some(100.0));
+        assert_eq!(radius_squared("norm(z) > 4"), Some(4.0));
+        assert_eq!(radius_squared("|z| < 2"), None);
+        assert_eq!(radius_squared("|z - 1| > 2"), None);
+        assert_eq!(radius_squared("re(z) > 2"), None);
+        assert!(compile("|z| > 2").unwrap().is_condition());
+        assert!(!compile("|z|").unwrap().is_condition());
+    }
+
+    #[test]
+    fn eval_matches_the_mandelbrot_step() {
+        let program = compile("z^2 + c").unwrap();
+        for (re, im) in [(0.0, 0.0), (0.3, -1.2), (-1.7, 0.01), (1e-5, 2.5)] {
+            let (z, c) = (Complex::new(re, im), Complex::new(im, re));
+            assert_eq!(program.eval(z, c, c), Mandelbrot.step(z, c));
+            let (z, c) = (z.cast::<DoubleDouble>(), c.cast::<DoubleDouble>());
+            assert_eq!(program.eval(z, c, c), Mandelbrot.step(z, c));
+        }
+    }
+}
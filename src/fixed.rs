@@ -0,0 +1,280 @@
+use std::ops::{Add, Mul, Sub};
+use std::str::FromStr;
+
+use num_bigint::{BigInt, BigUint, Sign};
+use num_traits::{One, ToPrimitive, Zero};
+
+use crate::complex::Complex;
+use crate::double::DoubleDouble;
+
+/// Fractional bits of the fixed point numbers rounded to double-double, past its
+/// 106 bits of mantissa for numbers down to 2^-50
+const DOUBLE_DOUBLE_BITS: u32 = 160;
+
+/// An arbitrary precision fixed point number: `value / 2^frac_bits`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fixed {
+    value: BigInt,
+    frac_bits: u32,
+}
+
+impl Fixed {
+    pub fn zero(frac_bits: u32) -> Fixed {
+        Fixed { value: BigInt::zero(), frac_bits }
+    }
+
+    /// Exact conversion from an `f64`, rounded to `frac_bits` fractional bits
+    pub fn from_f64(v: f64, frac_bits: u32) -> Fixed {
+        if v == 0.0 || !v.is_finite() {
+            return Fixed::zero(frac_bits);
+        }
+
+        // Decompose v = mantissa * 2^exp with an integer mantissa
+        let bits = v.to_bits();
+        let exp_bits = ((bits >> 52) & 0x7ff) as i64;
+        let (mantissa, exp) = if exp_bits == 0 {
+            ((bits & 0xf_ffff_ffff_ffff) << 1, -1075)
+        } else {
+            ((bits & 0xf_ffff_ffff_ffff) | (1 << 52), exp_bits - 1075)
+        };
+
+        let sign = if v < 0.0 { Sign::Minus } else { Sign::Plus };
+        let value = BigInt::from_biguint(sign, mantissa.into());
+        Fixed { value: shift(value, exp + frac_bits as i64), frac_bits }
+    }
+
+    /// Parse a decimal number of any length such as "-0.7436438870371587522225e-3"
+    pub fn from_decimal_str(s: &str, frac_bits: u32) -> Result<Fixed, String> {
+        let (digits, exp10) = parse_decimal(s)?;
+        let value = if exp10 >= 0 {
+            (digits * BigInt::from(10).pow(exp10 as u32)) << frac_bits
+        } else {
+            // Round the magnitude to nearest when dividing by the power of ten, so
+            // both signs round alike
+            let den = BigUint::from(10u32).pow(exp10.unsigned_abs() as u32);
+            let num = digits.magnitude() << (frac_bits + 1);
+            BigInt::from_biguint(digits.sign(), ((num / den) + BigUint::one()) >> 1u32)
+        };
+        Ok(Fixed { value, frac_bits })
+    }
+
+    /// Nearest `f64`
+    pub fn to_f64(&self) -> f64 {
+        // Keep the 64 most significant bits so the conversion cannot overflow
+        let excess = self.value.bits().saturating_sub(64) as i64;
+        let top = (&self.value >> excess as usize).to_f64().unwrap_or(0.0);
+        top * 2f64.powi((excess - self.frac_bits as i64) as i32)
+    }
+
+    /// Decimal representation rounded to `digits` fractional digits
+    pub fn to_decimal_string(&self, digits: usize) -> String {
+        // Round |value| * 10^digits / 2^frac_bits to an integer
+        let scaled = (self.value.magnitude() * BigUint::from(10u32).pow(digits as u32)) << 1u32;
+        let rounded = ((scaled >> self.frac_bits) + BigUint::one()) >> 1u32;
+
+        let text = format!("{:0>width$}", rounded.to_string(), width = digits + 1);
+        let (int_part, frac_part) = text.split_at(text.len() - digits);
+        let frac_part = frac_part.trim_end_matches('0');
+
+        let sign = if self.value.sign() == Sign::Minus && rounded != BigUint::zero() { "-" } else { "" };
+        if frac_part.is_empty() {
+            format!("{sign}{int_part}")
+        } else {
+            format!("{sign}{int_part}.{frac_part}")
+        }
+    }
+
+    /// Nearest double-double: the nearest `f64` plus the rounded remainder
+    pub fn to_double_double(&self) -> DoubleDouble {
+        let hi = self.to_f64();
+        let lo = (self - &Fixed::from_f64(hi, self.frac_bits)).to_f64();
+        DoubleDouble { hi, lo }
+    }
+
+    pub fn frac_bits(&self) -> u32 {
+        self.frac_bits
+    }
+
+    pub fn square(&self) -> Fixed {
+        self * self
+    }
+
+    fn check(&self, other: &Fixed) {
+        debug_assert_eq!(self.frac_bits, other.frac_bits, "mixed fixed point precisions");
+    }
+}
+
+/// Multiply by 2^n for any sign of n
+fn shift(v: BigInt, n: i64) -> BigInt {
+    if n >= 0 { v << n as usize } else { v >> (-n) as usize }
+}
+
+/// Largest power of ten accepted in a decimal number
+const MAX_DECIMAL_EXPONENT: i64 = 100_000;
+
+/// Split a decimal string into its digits as an integer and a power of ten
+fn parse_decimal(s: &str) -> Result<(BigInt, i64), String> {
+    let invalid = || format!("invalid decimal number \"{s}\"");
+    let s = s.trim();
+
+    let (mantissa, exp10) = match s.find(['e', 'E']) {
+        Some(i) => (&s[..i], s[i + 1..].parse::<i64>().map_err(|_| invalid())?),
+        None => (s, 0),
+    };
+    let (negative, mantissa) = match mantissa.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, mantissa.strip_prefix('+').unwrap_or(mantissa)),
+    };
+    let (int_part, frac_part) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+
+    let all_digits = int_part.chars().chain(frac_part.chars()).all(|c| c.is_ascii_digit());
+    if !all_digits || int_part.len() + frac_part.len() == 0 {
+        return Err(invalid());
+    }
+
+    let exp10 = exp10 - frac_part.len() as i64;
+    if exp10.abs() > MAX_DECIMAL_EXPONENT {
+        return Err(format!("decimal exponent of \"{s}\" is out of range"));
+    }
+
+    let digits: BigInt = format!("{int_part}{frac_part}").parse().map_err(|_| invalid())?;
+    Ok((if negative { -digits } else { digits }, exp10))
+}
+
+impl Add for &Fixed {
+    type Output = Fixed;
+
+    fn add(self, other: &Fixed) -> Fixed {
+        self.check(other);
+        Fixed { value: &self.value + &other.value, frac_bits: self.frac_bits }
+    }
+}
+
+impl Sub for &Fixed {
+    type Output = Fixed;
+
+    fn sub(self, other: &Fixed) -> Fixed {
+        self.check(other);
+        Fixed { value: &self.value - &other.value, frac_bits: self.frac_bits }
+    }
+}
+
+impl Mul for &Fixed {
+    type Output = Fixed;
+
+    fn mul(self, other: &Fixed) -> Fixed {
+        self.check(other);
+        Fixed { value: (&self.value * &other.value) >> self.frac_bits, frac_bits: self.frac_bits }
+    }
+}
+
+/// A complex number kept as the decimal strings it was written with, so it can be
+/// converted to any precision later
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecimalComplex {
+    re: String,
+    im: String,
+}
+
+impl DecimalComplex {
+    pub fn re(&self) -> &str {
+        &self.re
+    }
+
+    pub fn im(&self) -> &str {
+        &self.im
+    }
+
+    /// Nearest `f64` approximation
+    pub fn to_complex(&self) -> Complex {
+        Complex {
+            re: self.re.parse().expect("validated on construction"),
+            im: self.im.parse().expect("validated on construction"),
+        }
+    }
+
+    /// Nearest double-double approximation
+    pub fn to_double_double(&self) -> Complex<DoubleDouble> {
+        let (re, im) = self.to_fixed(DOUBLE_DOUBLE_BITS);
+        Complex { re: re.to_double_double(), im: im.to_double_double() }
+    }
+
+    /// Convert both parts to fixed point with `frac_bits` fractional bits
+    pub fn to_fixed(&self, frac_bits: u32) -> (Fixed, Fixed) {
+        (
+            Fixed::from_decimal_str(&self.re, frac_bits).expect("validated on construction"),
+            Fixed::from_decimal_str(&self.im, frac_bits).expect("validated on construction"),
+        )
+    }
+}
+
+impl From<(Fixed, Fixed)> for DecimalComplex {
+    /// Decimal form of a fixed point complex number, with enough digits to keep its precision
+    fn from((re, im): (Fixed, Fixed)) -> DecimalComplex {
+        let digits = |x: &Fixed| (x.frac_bits as f64 * std::f64::consts::LOG10_2).ceil() as usize + 1;
+        DecimalComplex { re: re.to_decimal_string(digits(&re)), im: im.to_decimal_string(digits(&im)) }
+    }
+}
+
+impl From<Complex> for DecimalComplex {
+    fn from(c: Complex) -> DecimalComplex {
+        // `Display` for f64 prints the shortest string that round-trips
+        DecimalComplex { re: c.re.to_string(), im: c.im.to_string() }
+    }
+}
+
+impl FromStr for DecimalComplex {
+    type Err = String;
+
+    /// Parse a complex number written as "re,im" with decimal parts of any length
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (re, im) = s
+            .split_once(',')
+            .ok_or_else(|| format!("expected \"re,im\", got \"{s}\""))?;
+        let (re, im) = (re.trim(), im.trim());
+        parse_decimal(re).map_err(|e| format!("invalid real part: {e}"))?;
+        parse_decimal(im).map_err(|e| format!("invalid imaginary part: {e}"))?;
+        Ok(DecimalComplex { re: re.to_string(), im: im.to_string() })
+    }
+}
+
+impl std::fmt::Display for DecimalComplex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{}", self.re, self.im)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_decimal_str_rounds_both_signs_alike() {
+        let units = |s: &str, frac_bits| Fixed::from_decimal_str(s, frac_bits).unwrap().value;
+        for (s, frac_bits, expected) in [("0.7", 0, 1), ("0.3", 0, 0), ("0.5", 0, 1), ("0.74", 2, 3), ("0.6", 2, 2)] {
+            assert_eq!(units(s, frac_bits), BigInt::from(expected), "{s}");
+            assert_eq!(units(&format!("-{s}"), frac_bits), BigInt::from(-expected), "-{s}");
+        }
+        for s in ["0.1", "0.7436438870371587522225e-3", "1.9999999999999999999999999999"] {
+            assert_eq!(units(&format!("-{s}"), 100), -units(s, 100), "-{s}");
+        }
+    }
+
+    #[test]
+    fn to_double_double_keeps_the_digits_f64_drops() {
+        let c: DecimalComplex = "0.1,-1.0000000000000000000000000000001".parse().unwrap();
+        let Complex { re, im } = c.to_double_double();
+        // 0.1 is 0.1000000000000000055511151231257827... in f64
+        assert_eq!(re.hi, 0.1);
+        assert!((re.lo + 5.551115123125783e-18).abs() < 1e-33, "{re:?}");
+        assert_eq!(im.hi, -1.0);
+        assert!((im.lo + 1e-31).abs() < 1e-45, "{im:?}");
+        assert_eq!(c.to_complex(), Complex::new(0.1, -1.0));
+    }
+
+    #[test]
+    fn to_double_double_is_exact_for_binary_fractions() {
+        let c: DecimalComplex = "-0.75,0.0009765625".parse().unwrap();
+        assert_eq!(c.to_double_double(), Complex::new(-0.75, 2f64.powi(-10)).cast::<DoubleDouble>());
+    }
+}
@@ -0,0 +1,414 @@
+use std::sync::Arc;
+
+use crate::complex::Complex;
+use crate::escape::Sample;
+use crate::expression::{is_name, is_reserved, Program, Variable};
+use crate::real::Real;
+
+/// A per-step map z -> f(z, c) iterated by the escape time algorithm, in the
+/// float type `T`
+pub trait FractalFormula<T: Real = f64>: Sync {
+    /// Compute z_{n+1} from z_n and the parameter c
+    fn step(&self, z: Complex<T>, c: Complex<T>) -> Complex<T>;
+
+    /// Compute z_{n+1} for the image point `pixel`, which is c itself or, for a
+    /// Julia set, z_0; only formulas that read the pixel need more than `step`
+    fn step_for_pixel(&self, z: Complex<T>, c: Complex<T>, _pixel: Complex<T>) -> Complex<T> {
+        self.step(z, c)
+    }
+
+    /// Escape radius; the orbit is considered unbounded once |z| exceeds it
+    fn bailout(&self) -> f64 {
+        2.0
+    }
+
+    /// Escape condition
+    fn escaped(&self, z: Complex<T>) -> bool {
+        z.cast::<f64>().magnitude_squared() > self.bailout() * self.bailout()
+    }
+
+    /// Growth degree of the map, used by the smooth coloring formula
+    fn degree(&self) -> f64 {
+        2.0
+    }
+
+    /// Derivative of the map with respect to z, `None` if it is not holomorphic
+    fn derivative(&self, _z: Complex<T>) -> Option<Complex<T>> {
+        None
+    }
+
+    /// Second derivative of the map with respect to z, `None` if it is not holomorphic
+    fn second_derivative(&self, _z: Complex<T>) -> Option<Complex<T>> {
+        None
+    }
+
+    /// Result for parameters known to be interior without iterating (z_0 = 0)
+    fn known_interior(&self, _c: Complex<T>) -> Option<Sample> {
+        None
+    }
+
+    /// Whether orbits of a parameter c start at z_0 = c rather than at the
+    /// critical point 0, for maps that are undefined at 0
+    fn starts_at_c(&self) -> bool {
+        false
+    }
+
+    /// Whether the bounded points have no holes, in the parameter plane and for
+    /// every Julia constant, so a loop of bounded points only encloses bounded
+    /// points; lets subdivision fill such loops without iterating their inside
+    fn bounded_set_is_full(&self) -> bool {
+        false
+    }
+}
+
+/// The classic Mandelbrot map z^2 + c
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Mandelbrot;
+
+impl<T: Real> FractalFormula<T> for Mandelbrot {
+    fn step(&self, z: Complex<T>, c: Complex<T>) -> Complex<T> {
+        z.square() + c
+    }
+
+    fn derivative(&self, z: Complex<T>) -> Option<Complex<T>> {
+        Some(z * T::from_f64(2.0))
+    }
+
+    fn second_derivative(&self, _z: Complex<T>) -> Option<Complex<T>> {
+        Some(Complex::new(T::from_f64(2.0), T::ZERO))
+    }
+
+    /// Main cardioid and period-2 bulb tests
+    /// https://en.wikipedia.org/wiki/Plotting_algorithms_for_the_Mandelbrot_set#Cardioid_/_bulb_checking
+    fn known_interior(&self, c: Complex<T>) -> Option<Sample> {
+        let k = T::from_f64;
+        let one = Complex::ONE;
+
+        let x = c.re - k(0.25);
+        let q = x * x + c.im * c.im;
+        if q * (q + x) <= k(0.25) * c.im * c.im {
+            // Attracting fixed point z = (1 - sqrt(1 - 4c)) / 2
+            let s = (one + Complex::new(k(-4.0) * c.re, k(-4.0) * c.im)).sqrt();
+            let z = Complex::new((T::ONE - s.re) / k(2.0), -s.im / k(2.0)).cast();
+            return Some(Sample { escape_time: None, z, period: Some(1), distance: None, multiplier: None, trap: None });
+        }
+
+        if (c.re + T::ONE) * (c.re + T::ONE) + c.im * c.im <= k(0.0625) {
+            // Attracting 2-cycle, a root of z^2 + z + c + 1 = 0
+            let s = Complex::new(k(-3.0) - k(4.0) * c.re, k(-4.0) * c.im).sqrt();
+            let z = Complex::new((s.re - T::ONE) / k(2.0), s.im / k(2.0)).cast();
+            return Some(Sample { escape_time: None, z, period: Some(2), distance: None, multiplier: None, trap: None });
+        }
+
+        None
+    }
+
+    fn bounded_set_is_full(&self) -> bool {
+        true
+    }
+}
+
+/// Burning Ship: (|Re z| + i|Im z|)^2 + c
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BurningShip;
+
+impl<T: Real> FractalFormula<T> for BurningShip {
+    fn step(&self, z: Complex<T>, c: Complex<T>) -> Complex<T> {
+        Complex::new(z.re.abs(), z.im.abs()).square() + c
+    }
+}
+
+/// Tricorn (Mandelbar): conj(z)^2 + c
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Tricorn;
+
+impl<T: Real> FractalFormula<T> for Tricorn {
+    fn step(&self, z: Complex<T>, c: Complex<T>) -> Complex<T> {
+        z.conj().square() + c
+    }
+}
+
+/// Multibrot: z^d + c for an integer or real exponent d
+#[derive(Debug, Clone, Copy)]
+pub struct Multibrot {
+    pub power: f64,
+}
+
+impl Multibrot {
+    /// `power` if escape times are defined for it: the smooth coloring needs
+    /// |power| > 1, as orbits grow like |z|^|power|
+    pub fn check_power(power: f64) -> Result<f64, String> {
+        if !(power.abs() > 1.0 && power.is_finite()) {
+            return Err(format!("the Multibrot power must be above 1 or below -1, got {power}"));
+        }
+        Ok(power)
+    }
+
+    /// z^(power + shift), exact for integer powers
+    fn pow<T: Real>(&self, z: Complex<T>, shift: f64) -> Complex<T> {
+        // Integer powers are exact and much cheaper than the polar form
+        if self.power.fract() == 0.0 && self.power.abs() <= i32::MAX as f64 {
+            z.powi(self.power as i32 + shift as i32)
+        } else {
+            z.powf(T::from_f64(self.power + shift))
+        }
+    }
+}
+
+impl<T: Real> FractalFormula<T> for Multibrot {
+    fn step(&self, z: Complex<T>, c: Complex<T>) -> Complex<T> {
+        self.pow(z, 0.0) + c
+    }
+
+    fn degree(&self) -> f64 {
+        self.power.abs()
+    }
+
+    /// Polynomial maps only: negative or fractional powers leave holes
+    fn bounded_set_is_full(&self) -> bool {
+        self.power >= 2.0 && self.power.fract() == 0.0
+    }
+
+    /// 0^power is infinite for negative powers
+    fn starts_at_c(&self) -> bool {
+        self.power < 0.0
+    }
+
+    fn derivative(&self, z: Complex<T>) -> Option<Complex<T>> {
+        Some(self.pow(z, -1.0) * T::from_f64(self.power))
+    }
+
+    fn second_derivative(&self, z: Complex<T>) -> Option<Complex<T>> {
+        Some(self.pow(z, -2.0) * T::from_f64(self.power * (self.power - 1.0)))
+    }
+}
+
+/// A formula written in the expression language of [`crate::expression`], such
+/// as `z^3 + c*sin(z)`, with a bailout condition such as `|z| > 2`
+#[derive(Clone)]
+pub struct CustomFormula {
+    source: String,
+    bailout_source: String,
+    params: Vec<(String, Complex)>,
+    step: Program,
+    bailout: Program,
+    /// Squared escape radius when the bailout is |z| > r, tested without the bailout program
+    radius_squared: Option<f64>,
+    degree: f64,
+}
+
+impl CustomFormula {
+    /// Bailout condition of formulas that do not set one
+    pub const DEFAULT_BAILOUT: &str = "|z| > 2";
+
+    /// Compile the step expression, which reads z, c and pixel, and the bailout
+    /// condition, which reads z; both may use the named constants in `params`
+    pub fn new(source: &str, bailout: &str, mut params: Vec<(String, Complex)>) -> Result<CustomFormula, String> {
+        for (name, _) in &params {
+            if !is_name(name) {
+                return Err(format!("invalid parameter name \"{name}\""));
+            }
+            if is_reserved(name) {
+                return Err(format!("parameter name \"{name}\" is already a variable, constant or function"));
+            }
+        }
+        params.sort_by(|a, b| a.0.cmp(&b.0));
+        if let Some(pair) = params.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            return Err(format!("parameter \"{}\" is given twice", pair[0].0));
+        }
+
+        let step = Program::compile(source, &[Variable::Z, Variable::C, Variable::Pixel], &params)?;
+        let bailout_program =
+            Program::compile(bailout, &[Variable::Z], &params).map_err(|e| format!("bailout: {e}"))?;
+        if !bailout_program.is_condition() {
+            return Err("bailout: expected a comparison such as |z| > 2".to_string());
+        }
+
+        // Smooth coloring needs the degree; formulas that do not grow like a power of z get 2
+        let degree = step.degree().filter(|&d| d > 1.0).unwrap_or(2.0);
+        Ok(CustomFormula {
+            source: source.to_string(),
+            bailout_source: bailout.to_string(),
+            params,
+            step,
+            radius_squared: bailout_program.escape_radius_squared(),
+            bailout: bailout_program,
+            degree,
+        })
+    }
+
+    /// The step expression as written
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The bailout condition as written
+    pub fn bailout_source(&self) -> &str {
+        &self.bailout_source
+    }
+
+    /// Named constants, sorted by name
+    pub fn params(&self) -> &[(String, Complex)] {
+        &self.params
+    }
+}
+
+impl PartialEq for CustomFormula {
+    fn eq(&self, other: &CustomFormula) -> bool {
+        self.source == other.source && self.bailout_source == other.bailout_source && self.params == other.params
+    }
+}
+
+impl std::fmt::Debug for CustomFormula {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CustomFormula")
+            .field("source", &self.source)
+            .field("bailout", &self.bailout_source)
+            .field("params", &self.params)
+            .finish()
+    }
+}
+
+impl<T: Real> FractalFormula<T> for CustomFormula {
+    fn step(&self, z: Complex<T>, c: Complex<T>) -> Complex<T> {
+        self.step.eval(z, c, c)
+    }
+
+    fn step_for_pixel(&self, z: Complex<T>, c: Complex<T>, pixel: Complex<T>) -> Complex<T> {
+        self.step.eval(z, c, pixel)
+    }
+
+    fn escaped(&self, z: Complex<T>) -> bool {
+        match self.radius_squared {
+            Some(radius_squared) => z.cast::<f64>().magnitude_squared() > radius_squared,
+            None => self.bailout.eval(z, Complex::ZERO, Complex::ZERO).re != T::ZERO,
+        }
+    }
+
+    fn degree(&self) -> f64 {
+        self.degree
+    }
+}
+
+/// Fractal formulas: the built-in ones, or one written by the user
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Fractal {
+    #[default]
+    Mandelbrot,
+    BurningShip,
+    Tricorn,
+    Multibrot { power: f64 },
+    Custom(Arc<CustomFormula>),
+}
+
+impl<T: Real> FractalFormula<T> for Fractal {
+    fn step(&self, z: Complex<T>, c: Complex<T>) -> Complex<T> {
+        match *self {
+            Fractal::Mandelbrot => Mandelbrot.step(z, c),
+            Fractal::BurningShip => BurningShip.step(z, c),
+            Fractal::Tricorn => Tricorn.step(z, c),
+            Fractal::Multibrot { power } => Multibrot { power }.step(z, c),
+            Fractal::Custom(ref custom) => custom.step(z, c),
+        }
+    }
+
+    fn step_for_pixel(&self, z: Complex<T>, c: Complex<T>, pixel: Complex<T>) -> Complex<T> {
+        match *self {
+            Fractal::Custom(ref custom) => custom.step_for_pixel(z, c, pixel),
+            _ => self.step(z, c),
+        }
+    }
+
+    fn escaped(&self, z: Complex<T>) -> bool {
+        match *self {
+            Fractal::Custom(ref custom) => custom.escaped(z),
+            _ => z.cast::<f64>().magnitude_squared() > 2.0 * 2.0,
+        }
+    }
+
+    fn degree(&self) -> f64 {
+        match *self {
+            Fractal::Multibrot { power } => FractalFormula::<T>::degree(&Multibrot { power }),
+            Fractal::Custom(ref custom) => FractalFormula::<T>::degree(&**custom),
+            _ => 2.0,
+        }
+    }
+
+    fn derivative(&self, z: Complex<T>) -> Option<Complex<T>> {
+        match *self {
+            Fractal::Mandelbrot => Mandelbrot.derivative(z),
+            Fractal::Multibrot { power } => Multibrot { power }.derivative(z),
+            Fractal::BurningShip | Fractal::Tricorn | Fractal::Custom(_) => None,
+        }
+    }
+
+    fn second_derivative(&self, z: Complex<T>) -> Option<Complex<T>> {
+        match *self {
+            Fractal::Mandelbrot => Mandelbrot.second_derivative(z),
+            Fractal::Multibrot { power } => Multibrot { power }.second_derivative(z),
+            Fractal::BurningShip | Fractal::Tricorn | Fractal::Custom(_) => None,
+        }
+    }
+
+    fn known_interior(&self, c: Complex<T>) -> Option<Sample> {
+        match *self {
+            Fractal::Mandelbrot => Mandelbrot.known_interior(c),
+            _ => None,
+        }
+    }
+
+    fn starts_at_c(&self) -> bool {
+        match *self {
+            Fractal::Multibrot { power } => FractalFormula::<T>::starts_at_c(&Multibrot { power }),
+            _ => false,
+        }
+    }
+
+    fn bounded_set_is_full(&self) -> bool {
+        match *self {
+            Fractal::Mandelbrot => FractalFormula::<T>::bounded_set_is_full(&Mandelbrot),
+            Fractal::Multibrot { power } => FractalFormula::<T>::bounded_set_is_full(&Multibrot { power }),
+            Fractal::BurningShip | Fractal::Tricorn | Fractal::Custom(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(names: &[&str]) -> Vec<(String, Complex)> {
+        names.iter().map(|name| (name.to_string(), Complex::new(0.5, 0.0))).collect()
+    }
+
+    #[test]
+    fn custom_formula_parameters() {
+        let formula = CustomFormula::new("z^2 + b*c + a", "|z| > 2", params(&["b", "a"])).unwrap();
+        assert_eq!(formula.params(), &params(&["a", "b"])[..]);
+
+        let error = |names: &[&str]| CustomFormula::new("z^2 + c", "|z| > 2", params(names)).unwrap_err();
+        assert_eq!(error(&["k", "x", "k"]), "parameter \"k\" is given twice");
+        assert_eq!(error(&["z"]), "parameter name \"z\" is already a variable, constant or function");
+        assert_eq!(error(&["pi"]), "parameter name \"pi\" is already a variable, constant or function");
+        assert_eq!(error(&["sin"]), "parameter name \"sin\" is already a variable, constant or function");
+        assert_eq!(error(&["2k"]), "invalid parameter name \"2k\"");
+        assert_eq!(error(&[""]), "invalid parameter name \"\"");
+    }
+
+    #[test]
+    fn custom_formula_bailout() {
+        let formula = CustomFormula::new("z^3 + c", CustomFormula::DEFAULT_BAILOUT, Vec::new()).unwrap();
+        assert_eq!(FractalFormula::<f64>::degree(&formula), 3.0);
+        assert!(!formula.escaped(Complex::new(2.0, 0.0)));
+        assert!(formula.escaped(Complex::new(2.0, 0.1)));
+
+        let formula = CustomFormula::new("sin(z) * c", "|im(z)| > 50", Vec::new()).unwrap();
+        assert_eq!(FractalFormula::<f64>::degree(&formula), 2.0);
+        assert!(!formula.escaped(Complex::new(1000.0, 49.0)));
+        assert!(formula.escaped(Complex::new(0.0, -51.0)));
+
+        let error = |bailout| CustomFormula::new("z^2 + c", bailout, Vec::new()).unwrap_err();
+        assert_eq!(error("|z|"), "bailout: expected a comparison such as |z| > 2");
+        assert!(error("|c| > 2").starts_with("bailout: column 2: \"c\" cannot be used here"));
+    }
+}
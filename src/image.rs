@@ -0,0 +1,97 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Seek, Write};
+use std::path::Path;
+
+use image::{ImageFormat, Rgb, RgbImage};
+
+use crate::color::Color;
+
+/// A rendered image, stored row by row
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+impl Image {
+    /// Convert into an `image` crate buffer
+    pub fn to_rgb_image(&self) -> RgbImage {
+        let mut imgbuf = RgbImage::new(self.width as u32, self.height as u32);
+
+        for (i, pixel) in self.pixels.iter().enumerate() {
+            let x = (i % self.width) as u32;
+            let y = (i / self.width) as u32;
+            imgbuf.put_pixel(x, y, Rgb([pixel.r, pixel.g, pixel.b]));
+        }
+
+        imgbuf
+    }
+}
+
+/// Write the image into a PNG file
+pub fn write_png<P: AsRef<Path>>(filename: P, img: &Image) -> std::io::Result<()> {
+    img.to_rgb_image()
+        .save_with_format(filename, ImageFormat::Png)
+        .map_err(std::io::Error::other)
+}
+
+/// Write the image into a PNG file with `(keyword, text)` pairs stored in iTXt chunks
+pub fn write_png_with_text<P: AsRef<Path>>(filename: P, img: &Image, text: &[(&str, &str)]) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(filename)?);
+    let mut png_writer = png_encoder(&mut writer, img.width, img.height, text)?
+        .write_header()
+        .map_err(std::io::Error::other)?;
+
+    let data: Vec<u8> = img.pixels.iter().flat_map(|p| [p.r, p.g, p.b]).collect();
+    png_writer.write_image_data(&data).map_err(std::io::Error::other)?;
+    png_writer.finish().map_err(std::io::Error::other)?;
+    writer.flush()
+}
+
+/// 8-bit RGB PNG encoder carrying `(keyword, text)` pairs in iTXt chunks
+pub(crate) fn png_encoder<W: Write>(
+    writer: W,
+    width: usize,
+    height: usize,
+    text: &[(&str, &str)],
+) -> std::io::Result<png::Encoder<'static, W>> {
+    let (png_width, png_height) = match (u32::try_from(width), u32::try_from(height)) {
+        (Ok(w), Ok(h)) if w > 0 && h > 0 => (w, h),
+        _ => return Err(std::io::Error::other(format!("cannot encode a {width}x{height} PNG"))),
+    };
+
+    let mut encoder = png::Encoder::new(writer, png_width, png_height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    for &(keyword, value) in text {
+        encoder.add_itxt_chunk(keyword.to_string(), value.to_string()).map_err(std::io::Error::other)?;
+    }
+    Ok(encoder)
+}
+
+/// Read the `(keyword, text)` pairs of the tEXt, zTXt and iTXt chunks of a PNG file
+pub fn read_png_text<P: AsRef<Path>>(filename: P) -> std::io::Result<Vec<(String, String)>> {
+    let decoder = png::Decoder::new(BufReader::new(File::open(filename)?));
+    let reader = decoder.read_info().map_err(std::io::Error::other)?;
+    let info = reader.info();
+
+    let mut text = Vec::new();
+    for chunk in &info.uncompressed_latin1_text {
+        text.push((chunk.keyword.clone(), chunk.text.clone()));
+    }
+    for chunk in &info.compressed_latin1_text {
+        text.push((chunk.keyword.clone(), chunk.get_text().map_err(std::io::Error::other)?));
+    }
+    for chunk in &info.utf8_text {
+        text.push((chunk.keyword.clone(), chunk.get_text().map_err(std::io::Error::other)?));
+    }
+    Ok(text)
+}
+
+/// Encode the image as PNG into any seekable writer
+pub fn encode_png<W: Write + Seek>(writer: &mut W, img: &Image) -> std::io::Result<()> {
+    img.to_rgb_image()
+        .write_to(writer, ImageFormat::Png)
+        .map_err(std::io::Error::other)
+}
@@ -0,0 +1,63 @@
+//! Escape time fractal renderer (Mandelbrot, Julia, Burning Ship, Tricorn, Multibrot),
+//! plus Buddhabrot and Newton fractals.
+//!
+//! Build a [`RenderParams`], call [`render`] to get an [`Image`], then encode
+//! it with [`write_png`] or [`encode_png`]. To try several palettes on the same
+//! view, [`compute_with_progress`] keeps the raw [`EscapeData`] which can be saved
+//! and colored later.
+
+pub mod animated;
+pub mod animation;
+pub mod antialias;
+pub mod buddhabrot;
+pub mod checkpoint;
+pub mod color;
+pub mod coloring;
+pub mod complex;
+pub mod data;
+pub mod double;
+pub mod escape;
+pub mod expression;
+pub mod fixed;
+pub mod formula;
+pub mod image;
+pub mod newton;
+pub mod palette;
+pub mod perturbation;
+pub mod precision;
+pub mod real;
+pub mod render;
+pub mod scene;
+pub mod stream;
+pub mod subdivision;
+pub mod trap;
+pub mod viewport;
+
+pub use animated::{encode_apng, encode_gif, write_apng, write_gif, AnimationOptions};
+pub use animation::{Animation, Easing, Keyframe};
+pub use antialias::{Antialias, Sampling};
+pub use buddhabrot::{
+    compute_buddhabrot_with_progress, render_buddhabrot_with_progress, BuddhabrotParams, Histogram,
+};
+pub use checkpoint::compute_resumable;
+pub use color::Color;
+pub use coloring::{Coloring, Interior};
+pub use complex::Complex;
+pub use double::DoubleDouble;
+pub use data::{read_data, write_data, EscapeData};
+pub use fixed::DecimalComplex;
+pub use formula::{CustomFormula, Fractal, FractalFormula};
+pub use image::{encode_png, read_png_text, write_png, write_png_with_text, Image};
+pub use newton::{render_newton_with_progress, NewtonParams, Polynomial};
+pub use palette::{Gradient, NamedGradient, Palette, Wrap};
+pub use perturbation::{compute_deep_with_progress, render_deep_with_progress};
+pub use precision::Precision;
+pub use real::Real;
+pub use render::{
+    compute_double_double_with_progress, compute_formula_with_progress, compute_with_progress, render,
+    render_double_double_with_progress, render_formula_with_progress, render_with_progress, RenderParams,
+};
+pub use scene::{read_png_scene, read_scene, write_png_with_scene, write_scene, OutputFormat, Scene};
+pub use stream::{encode_png_streaming, write_png_streaming};
+pub use trap::{ImageTrap, OrbitTrap, TrapHit};
+pub use viewport::Viewport;
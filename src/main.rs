@@ -1,15 +1,365 @@
-use std::ops::Add;
-use std::iter::successors;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
-use clap::Parser;
-use image::{Rgb, RgbImage};
-use indicatif::{ProgressBar, ProgressStyle, ParallelProgressIterator};
-use rayon::prelude::*;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+
+use fractal::antialias::supersample;
+use fractal::formula::Multibrot;
+use fractal::scene::{FractalSettings, OutputSettings, PaletteSettings, ViewSettings};
+use fractal::{
+    compute_deep_with_progress, compute_double_double_with_progress, compute_with_progress, compute_resumable,
+    read_data, read_png_scene, read_scene, render_buddhabrot_with_progress, render_newton_with_progress, write_apng,
+    write_data, write_gif, write_png, write_png_streaming, write_png_with_scene, write_scene, Animation,
+    AnimationOptions, Antialias, BuddhabrotParams, Coloring, Complex, CustomFormula, DecimalComplex, Easing,
+    EscapeData, Fractal, Gradient, Image, ImageTrap, Interior, Keyframe, NamedGradient, NewtonParams, OrbitTrap,
+    OutputFormat, Palette, Polynomial, Precision, RenderParams, Sampling, Scene, Viewport, Wrap,
+};
 
 // Command line arguments
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-struct Args {
+#[command(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    render: RenderArgs,
+}
+
+impl Cli {
+    /// Image settings of the commands that render escape time fractals
+    fn image_args(&self) -> Option<&ImageArgs> {
+        match &self.command {
+            None => Some(&self.render.image),
+            Some(Command::Animate(args)) => Some(&args.image),
+            Some(Command::Cycle(args)) => Some(&args.image),
+            Some(_) => None,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Color escape data saved with --save-data using a new palette
+    Recolor(RecolorArgs),
+    /// Render a keyframed zoom animation as numbered PNG frames, GIF or APNG
+    Animate(AnimateArgs),
+    /// Render one view and loop its palette offset through a full cycle
+    Cycle(CycleArgs),
+    /// Render the density of escaping orbits (Buddhabrot, Nebulabrot, anti-Buddhabrot)
+    Buddhabrot(BuddhabrotArgs),
+    /// Render the basins of attraction of Newton's method for a polynomial
+    Newton(NewtonArgs),
+}
+
+// Arguments of the default render command
+#[derive(Args, Debug)]
+struct RenderArgs {
+    /// Load all settings from a TOML or JSON scene file; flags given on the command line override it
+    #[arg(long)]
+    scene: Option<PathBuf>,
+
+    /// Render again from the parameters recorded in a PNG written by this program; flags override them
+    #[arg(long, conflicts_with = "scene")]
+    from_image: Option<PathBuf>,
+
+    /// Write the effective scene to this TOML or JSON file ("-" prints TOML), then render it
+    #[arg(long)]
+    dump_scene: Option<PathBuf>,
+
+    /// Center of the view in the complex plane, as "re,im" (decimals of any length)
+    #[arg(long, default_value = "-0.5,0", allow_hyphen_values = true)]
+    center: DecimalComplex,
+
+    /// Zoom factor (1 shows the whole set)
+    #[arg(long, default_value_t = 1.0, value_parser = parse_zoom)]
+    zoom: f64,
+
+    #[command(flatten)]
+    image: ImageArgs,
+
+    /// Also save the raw per-pixel escape data to this file, for use with `recolor`
+    /// (distance estimates are only included with a distance based --coloring)
+    #[arg(long)]
+    save_data: Option<String>,
+
+    /// Compute and write the PNG in strips of rows, so memory stays bounded for very large images
+    #[arg(long)]
+    stream: bool,
+
+    /// Save progress to this file while rendering, so an interrupted render can be resumed;
+    /// it is deleted once the image is written
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+
+    /// Continue the render saved in --checkpoint, which must have the same view and iteration settings
+    #[arg(long, requires = "checkpoint")]
+    resume: bool,
+
+    /// Output filename
+    #[arg(long, default_value = "fractal.png")]
+    output: String,
+}
+
+impl RenderArgs {
+    /// Scene described by the flags alone
+    fn to_scene(&self) -> Scene {
+        let params = self.image.to_params(&self.center, self.zoom);
+        Scene {
+            view: ViewSettings { center: self.center.clone(), zoom: self.zoom },
+            fractal: FractalSettings {
+                formula: params.fractal,
+                julia: params.julia,
+                max_iter: params.max_iter,
+                precision: self.image.precision(),
+                subdivide: params.subdivide,
+            },
+            palette: PaletteSettings {
+                name: self.image.palette.palette.into(),
+                gradient: self.image.palette.gradient.clone(),
+                offset: params.palette.offset,
+                density: params.palette.density,
+                wrap: params.palette.wrap,
+            },
+            coloring: params.coloring,
+            interior: params.interior,
+            antialias: params.antialias,
+            trap: params.trap,
+            output: OutputSettings {
+                file: self.output.clone(),
+                width: self.image.width,
+                height: self.image.height,
+                format: if self.stream { OutputFormat::PngStream } else { OutputFormat::Png },
+                save_data: self.save_data.clone(),
+            },
+        }
+    }
+
+    /// Apply the flags given on the command line on top of a scene file
+    fn override_scene(&self, mut scene: Scene, matches: &ArgMatches) -> std::io::Result<Scene> {
+        let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+        let cli = self.to_scene();
+
+        if given("center") {
+            scene.view.center = cli.view.center;
+        }
+        if given("zoom") {
+            scene.view.zoom = cli.view.zoom;
+        }
+
+        if given("fractal") || given("formula") {
+            scene.fractal.formula = cli.fractal.formula;
+        } else if let (true, Fractal::Multibrot { power }) = (given("power"), &mut scene.fractal.formula) {
+            *power = self.image.power;
+        } else if let (true, Fractal::Custom(custom)) = (given("bailout") || given("params"), &scene.fractal.formula) {
+            let bailout = if given("bailout") { &self.image.bailout } else { custom.bailout_source() };
+            let params = if given("params") { self.image.params.clone() } else { custom.params().to_vec() };
+            let custom = CustomFormula::new(custom.source(), bailout, params).map_err(std::io::Error::other)?;
+            scene.fractal.formula = Fractal::Custom(Arc::new(custom));
+        }
+        if given("julia") {
+            scene.fractal.julia = cli.fractal.julia;
+        }
+        if given("max_iter") {
+            scene.fractal.max_iter = cli.fractal.max_iter;
+        }
+        if given("deep") || given("precision") {
+            scene.fractal.precision = cli.fractal.precision;
+        }
+        if given("subdivide") {
+            scene.fractal.subdivide = cli.fractal.subdivide;
+        }
+
+        if given("palette") {
+            scene.palette.name = cli.palette.name;
+        }
+        if given("gradient") {
+            scene.palette.gradient = cli.palette.gradient;
+        }
+        if given("palette_offset") {
+            scene.palette.offset = cli.palette.offset;
+        }
+        if given("palette_density") {
+            scene.palette.density = cli.palette.density;
+        }
+        if given("palette_wrap") {
+            scene.palette.wrap = cli.palette.wrap;
+        }
+
+        if given("coloring") {
+            scene.coloring = cli.coloring;
+        } else if let (true, Coloring::Shaded { width } | Coloring::LineArt { width }) =
+            (given("boundary_width"), &mut scene.coloring)
+        {
+            *width = self.image.coloring.boundary_width;
+        }
+        if given("interior") {
+            scene.interior = cli.interior;
+        }
+        if given("trap") {
+            scene.trap = cli.trap;
+        } else if let Some(trap) = &mut scene.trap {
+            self.image.trap.override_trap(trap, given);
+        }
+
+        if given("samples") {
+            scene.antialias.samples = cli.antialias.samples;
+        }
+        if given("sampling") {
+            scene.antialias.sampling = cli.antialias.sampling;
+        }
+        if given("adaptive") {
+            scene.antialias.adaptive = cli.antialias.adaptive;
+        } else if let (true, Some(threshold)) = (given("adaptive_threshold"), &mut scene.antialias.adaptive) {
+            *threshold = self.image.adaptive_threshold;
+        }
+        if given("seed") {
+            scene.antialias.seed = cli.antialias.seed;
+        }
+
+        if given("output") {
+            scene.output.file = cli.output.file;
+        }
+        if given("width") {
+            scene.output.width = cli.output.width;
+        }
+        if given("height") {
+            scene.output.height = cli.output.height;
+        }
+        if given("stream") {
+            scene.output.format = OutputFormat::PngStream;
+        }
+        if given("save_data") {
+            scene.output.save_data = cli.output.save_data;
+        }
+
+        Ok(scene)
+    }
+}
+
+#[derive(Args, Debug)]
+struct AnimateArgs {
+    /// View at a point of the animation as "re,im@zoom"; give at least two, spread evenly over the frames
+    #[arg(long = "keyframe", required = true, allow_hyphen_values = true)]
+    keyframes: Vec<Keyframe>,
+
+    /// Number of frames
+    #[arg(long, default_value_t = 120)]
+    frames: usize,
+
+    /// Easing curve between keyframes
+    #[arg(long, value_enum, default_value_t = EasingMode::EaseInOut)]
+    easing: EasingMode,
+
+    #[command(flatten)]
+    image: ImageArgs,
+
+    #[command(flatten)]
+    output: AnimationOutputArgs,
+}
+
+#[derive(Args, Debug)]
+struct CycleArgs {
+    /// Center of the view in the complex plane, as "re,im" (decimals of any length)
+    #[arg(long, default_value = "-0.5,0", allow_hyphen_values = true)]
+    center: DecimalComplex,
+
+    /// Zoom factor (1 shows the whole set)
+    #[arg(long, default_value_t = 1.0, value_parser = parse_zoom)]
+    zoom: f64,
+
+    /// Number of frames in one palette cycle
+    #[arg(long, default_value_t = 64)]
+    frames: usize,
+
+    #[command(flatten)]
+    image: ImageArgs,
+
+    #[command(flatten)]
+    output: AnimationOutputArgs,
+}
+
+#[derive(Args, Debug)]
+struct BuddhabrotArgs {
+    /// Center of the view in the complex plane, as "re,im"
+    #[arg(long, default_value = "-0.5,0", allow_hyphen_values = true)]
+    center: Complex,
+
+    /// Zoom factor (1 shows the whole set)
+    #[arg(long, default_value_t = 1.0, value_parser = parse_zoom)]
+    zoom: f64,
+
+    /// Image width in pixels
+    #[arg(long, default_value_t = 1000)]
+    width: usize,
+
+    /// Image height in pixels
+    #[arg(long, default_value_t = 1000)]
+    height: usize,
+
+    /// Fractal formula to iterate
+    #[arg(long, value_enum, default_value_t = FractalKind::Mandelbrot)]
+    fractal: FractalKind,
+
+    /// Exponent d of the Multibrot formula z^d + c (integer or real)
+    #[arg(long, default_value_t = 3.0, allow_hyphen_values = true, value_parser = parse_power)]
+    power: f64,
+
+    /// Number of random parameters c to trace
+    #[arg(long, default_value_t = 10_000_000)]
+    samples: u64,
+
+    /// Iteration limit of a grayscale Buddhabrot
+    #[arg(long, default_value_t = 1000)]
+    max_iter: usize,
+
+    /// Skip escaping orbits shorter than this
+    #[arg(long, default_value_t = 0)]
+    min_iter: usize,
+
+    /// Nebulabrot: iteration limits of the red, green and blue channels as "r,g,b", overrides --max-iter
+    #[arg(long, value_parser = parse_channel_limits)]
+    nebula: Option<[usize; 3]>,
+
+    /// Count the orbits that never escape instead (anti-Buddhabrot)
+    #[arg(long)]
+    anti: bool,
+
+    /// Seed of the random parameters
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Brightness curve exponent: counts are mapped through (count / max)^(1 / gamma)
+    #[arg(long, default_value_t = 2.0)]
+    gamma: f64,
+
+    /// Output filename
+    #[arg(long, default_value = "buddhabrot.png")]
+    output: String,
+}
+
+#[derive(Args, Debug)]
+struct NewtonArgs {
+    /// Coefficients from the highest degree down, each real or "re,im", as "1;0;0;-1" for z^3 - 1
+    #[arg(long, default_value = "1;0;0;-1", allow_hyphen_values = true)]
+    coefficients: Polynomial,
+
+    /// A root "re,im" of the polynomial; give each root (repeated for multiplicity) instead of --coefficients
+    #[arg(long = "root", allow_hyphen_values = true, conflicts_with = "coefficients")]
+    roots: Vec<Complex>,
+
+    /// Center of the view in the complex plane, as "re,im"
+    #[arg(long, default_value = "0,0", allow_hyphen_values = true)]
+    center: Complex,
+
+    /// Zoom factor
+    #[arg(long, default_value_t = 1.0, value_parser = parse_zoom)]
+    zoom: f64,
+
     /// Image width in pixels
     #[arg(long, default_value_t = 1000)]
     width: usize,
@@ -17,142 +367,864 @@ struct Args {
     /// Image height in pixels
     #[arg(long, default_value_t = 1000)]
     height: usize,
-    
+
+    /// Maximum number of Newton steps
+    #[arg(long, default_value_t = 100)]
+    max_iter: usize,
+
+    /// Step length below which the iteration has converged
+    #[arg(long, default_value_t = 1e-10)]
+    tolerance: f64,
+
+    /// Darkening per step: colors are scaled by exp(-shading * steps)
+    #[arg(long, default_value_t = 0.08)]
+    shading: f64,
+
+    /// Output filename
+    #[arg(long, default_value = "newton.png")]
+    output: String,
+}
+
+/// Parse a zoom factor, which must be finite and positive
+fn parse_zoom(s: &str) -> Result<f64, String> {
+    Viewport::check_zoom(s.trim().parse::<f64>().map_err(|e| format!("invalid zoom: {e}"))?)
+}
+
+/// Parse a Multibrot power
+fn parse_power(s: &str) -> Result<f64, String> {
+    Multibrot::check_power(s.trim().parse::<f64>().map_err(|e| format!("invalid power: {e}"))?)
+}
+
+/// Parse three iteration limits written as "r,g,b"
+fn parse_channel_limits(s: &str) -> Result<[usize; 3], String> {
+    let limits: Vec<usize> = s
+        .split(',')
+        .map(|part| part.trim().parse().map_err(|_| format!("invalid iteration limit \"{part}\"")))
+        .collect::<Result<_, _>>()?;
+    limits.try_into().map_err(|_| format!("expected \"r,g,b\", got \"{s}\""))
+}
+
+// Where the frames of an animation go
+#[derive(Args, Debug)]
+struct AnimationOutputArgs {
+    /// Directory receiving frame_00001.png, frame_00002.png, ... (default "frames" without --gif or --apng)
+    #[arg(long)]
+    output_dir: Option<PathBuf>,
+
+    /// Also encode the frames as an animated GIF
+    #[arg(long)]
+    gif: Option<PathBuf>,
+
+    /// Also encode the frames as an animated PNG
+    #[arg(long)]
+    apng: Option<PathBuf>,
+
+    /// Playback speed of the GIF and APNG, in frames per second
+    #[arg(long, default_value_t = 25.0)]
+    fps: f64,
+
+    /// Dither GIF frames when reducing them to 256 colors
+    #[arg(long)]
+    dither: bool,
+}
+
+impl AnimationOutputArgs {
+    /// Directory for the numbered PNG frames, if any
+    fn frame_dir(&self) -> Option<PathBuf> {
+        match (&self.output_dir, &self.gif, &self.apng) {
+            (Some(dir), _, _) => Some(dir.clone()),
+            (None, None, None) => Some(PathBuf::from("frames")),
+            _ => None,
+        }
+    }
+
+    /// Whether frames must be kept in memory for an animated file
+    fn collects_frames(&self) -> bool {
+        self.gif.is_some() || self.apng.is_some()
+    }
+
+    fn to_options(&self) -> AnimationOptions {
+        AnimationOptions { fps: self.fps, dither: self.dither, ..AnimationOptions::default() }
+    }
+}
+
+// Settings shared by every rendered image
+#[derive(Args, Debug)]
+struct ImageArgs {
+    /// Image width in pixels
+    #[arg(long, default_value_t = 1000)]
+    width: usize,
+
+    /// Image height in pixels
+    #[arg(long, default_value_t = 1000)]
+    height: usize,
+
+    /// Arithmetic the orbits are computed in; auto picks it from the pixel spacing
+    #[arg(long, value_enum, default_value_t = PrecisionMode::Auto)]
+    precision: PrecisionMode,
+
+    /// Always use perturbation deep zoom, same as --precision perturbation
+    #[arg(long)]
+    deep: bool,
+
+    /// Fill rectangles whose border never escapes instead of iterating them
+    /// (Mariani-Silver subdivision, which can miss details thinner than a pixel)
+    #[arg(long)]
+    subdivide: bool,
+
+    /// Fractal formula to iterate
+    #[arg(long, value_enum, default_value_t = FractalKind::Mandelbrot)]
+    fractal: FractalKind,
+
+    /// Exponent d of the Multibrot formula z^d + c (integer or real)
+    #[arg(long, default_value_t = 3.0, allow_hyphen_values = true, value_parser = parse_power)]
+    power: f64,
+
+    /// Iterate this expression of z, c, pixel and the --param constants instead of --fractal, as "z^3 + c*sin(z)"
+    #[arg(long, allow_hyphen_values = true)]
+    formula: Option<String>,
+
+    /// Escape condition of --formula, an expression of z such as "|z| > 2" or "re(z)^2 > 50"
+    #[arg(long, default_value = CustomFormula::DEFAULT_BAILOUT, allow_hyphen_values = true)]
+    bailout: String,
+
+    /// A named constant of --formula, as "name=re,im"; may be repeated
+    #[arg(long = "param", value_name = "NAME=RE,IM", value_parser = parse_param, allow_hyphen_values = true)]
+    params: Vec<(String, Complex)>,
+
+    /// Render the Julia set for the constant c = "re,im" instead of the Mandelbrot set
+    #[arg(long, allow_hyphen_values = true)]
+    julia: Option<Complex>,
 
     /// Maximum number of iterations for the escape time algorithm
     #[arg(long, default_value_t = 1000)]
     max_iter: usize,
 
+    #[command(flatten)]
+    palette: PaletteArgs,
+
+    #[command(flatten)]
+    coloring: ColoringArgs,
+
+    #[command(flatten)]
+    trap: TrapArgs,
+
+    /// Supersampling: N x N samples per pixel
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    samples: u16,
+
+    /// Placement of the subpixel samples
+    #[arg(long, value_enum, default_value_t = SamplingMode::Grid)]
+    sampling: SamplingMode,
+
+    /// Only supersample pixels that differ strongly from their neighbours
+    #[arg(long)]
+    adaptive: bool,
+
+    /// Largest color channel difference (0-255) between neighbours before a pixel is supersampled
+    #[arg(long, default_value_t = 16)]
+    adaptive_threshold: u8,
+
+    /// Seed for randomized sampling
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+}
+
+impl ImageArgs {
+    /// The --formula if given, compiled, or else the --fractal
+    fn fractal(&self) -> Result<Fractal, String> {
+        match &self.formula {
+            Some(source) => Ok(Fractal::Custom(Arc::new(CustomFormula::new(source, &self.bailout, self.params.clone())?))),
+            None => Ok(self.fractal.to_fractal(self.power)),
+        }
+    }
+
+    fn to_params(&self, center: &DecimalComplex, zoom: f64) -> RenderParams {
+        RenderParams {
+            width: self.width,
+            height: self.height,
+            viewport: Viewport::new(center.to_complex(), zoom),
+            fractal: self.fractal().expect("formulas are checked by main"),
+            julia: self.julia,
+            max_iter: self.max_iter,
+            palette: self.palette.to_palette(),
+            coloring: self.coloring.to_coloring(),
+            interior: self.coloring.interior.into(),
+            antialias: Antialias {
+                samples: self.samples as usize,
+                sampling: self.sampling.into(),
+                adaptive: self.adaptive.then_some(self.adaptive_threshold),
+                seed: self.seed,
+            },
+            trap: self.trap.to_trap(),
+            subdivide: self.subdivide,
+        }
+    }
+
+    fn precision(&self) -> Precision {
+        if self.deep { Precision::Perturbation } else { self.precision.into() }
+    }
+}
+
+// Orbit trap settings
+#[derive(Args, Debug)]
+struct TrapArgs {
+    /// Orbit trap shape, for --coloring trap
+    #[arg(long, value_enum, requires_if("image", "trap_image"))]
+    trap: Option<TrapShape>,
+
+    /// Center of the point, cross, circle and image traps, or a point of the line trap, as "re,im"
+    #[arg(long, default_value = "0,0", allow_hyphen_values = true)]
+    trap_center: Complex,
+
+    /// Radius of the circle trap
+    #[arg(long, default_value_t = 0.5)]
+    trap_radius: f64,
+
+    /// Angle of the line trap from the real axis, in degrees
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    trap_angle: f64,
+
+    /// Image of the image trap; its opaque pixels color the orbits that land on them
+    #[arg(long, value_parser = parse_trap_image)]
+    trap_image: Option<ImageTrap>,
+
+    /// Width of the image trap in the complex plane
+    #[arg(long, default_value_t = 1.0)]
+    trap_size: f64,
+}
+
+impl TrapArgs {
+    fn to_trap(&self) -> Option<OrbitTrap> {
+        let center = self.trap_center;
+        Some(match self.trap? {
+            TrapShape::Point => OrbitTrap::Point { center },
+            TrapShape::Line => OrbitTrap::Line { point: center, angle: self.trap_angle },
+            TrapShape::Cross => OrbitTrap::Cross { center },
+            TrapShape::Circle => OrbitTrap::Circle { center, radius: self.trap_radius },
+            TrapShape::Image => {
+                let mut image = self.trap_image.clone().expect("required by clap");
+                (image.center, image.size) = (center, self.trap_size);
+                OrbitTrap::Image(image)
+            }
+        })
+    }
+
+    /// Apply the trap flags given on the command line to a trap of a scene file
+    fn override_trap(&self, trap: &mut OrbitTrap, given: impl Fn(&str) -> bool) {
+        match trap {
+            OrbitTrap::Point { center } | OrbitTrap::Cross { center } | OrbitTrap::Line { point: center, .. } => {
+                if given("trap_center") {
+                    *center = self.trap_center;
+                }
+            }
+            OrbitTrap::Circle { center, radius } => {
+                if given("trap_center") {
+                    *center = self.trap_center;
+                }
+                if given("trap_radius") {
+                    *radius = self.trap_radius;
+                }
+            }
+            OrbitTrap::Image(image) => {
+                if let Some(new) = &self.trap_image {
+                    let (center, size) = (image.center, image.size);
+                    *image = new.clone();
+                    (image.center, image.size) = (center, size);
+                }
+                if given("trap_center") {
+                    image.center = self.trap_center;
+                }
+                if given("trap_size") {
+                    image.size = self.trap_size;
+                }
+            }
+        }
+        if let (true, OrbitTrap::Line { angle, .. }) = (given("trap_angle"), trap) {
+            *angle = self.trap_angle;
+        }
+    }
+}
+
+/// Parse a constant of a custom formula written as "name=re,im"
+fn parse_param(s: &str) -> Result<(String, Complex), String> {
+    let (name, value) = s.split_once('=').ok_or_else(|| format!("expected \"name=re,im\", got \"{s}\""))?;
+    Ok((name.trim().to_string(), value.parse()?))
+}
+
+/// Load the bitmap of an image trap; its placement is set from the other flags
+fn parse_trap_image(path: &str) -> Result<ImageTrap, String> {
+    ImageTrap::open(path, Complex::ZERO, 1.0)
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum TrapShape {
+    Point,
+    Line,
+    Cross,
+    Circle,
+    Image,
+}
+
+#[derive(Args, Debug)]
+struct RecolorArgs {
+    /// Escape data file written with --save-data
+    #[arg(long)]
+    input: String,
+
+    #[command(flatten)]
+    palette: PaletteArgs,
+
+    #[command(flatten)]
+    coloring: ColoringArgs,
+
     /// Output filename
     #[arg(long, default_value = "fractal.png")]
     output: String,
 }
-#[derive(Debug, Clone, Copy)]
-struct Complex {
-    re: f64,
-    im: f64,
+
+#[derive(Args, Debug)]
+struct ColoringArgs {
+    /// How escape data is turned into colors
+    #[arg(long, value_enum, default_value_t = ColoringMode::EscapeTime)]
+    coloring: ColoringMode,
+
+    /// Boundary width in pixels for the shaded and line-art colorings
+    #[arg(long, default_value_t = 1.0)]
+    boundary_width: f64,
+
+    /// How points inside the set are colored
+    #[arg(long, value_enum, default_value_t = InteriorMode::Black)]
+    interior: InteriorMode,
 }
 
-impl Complex {
-    fn magnitude(&self) -> f64 {
-        (self.re * self.re + self.im * self.im).sqrt()
+impl ColoringArgs {
+    fn to_coloring(&self) -> Coloring {
+        match self.coloring {
+            ColoringMode::EscapeTime => Coloring::EscapeTime,
+            ColoringMode::Distance => Coloring::Distance,
+            ColoringMode::Shaded => Coloring::Shaded { width: self.boundary_width },
+            ColoringMode::LineArt => Coloring::LineArt { width: self.boundary_width },
+            ColoringMode::Histogram => Coloring::Histogram,
+            ColoringMode::Trap => Coloring::Trap,
+        }
     }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ColoringMode {
+    /// Palette over the smooth escape time
+    EscapeTime,
+    /// Palette over the estimated distance to the set
+    Distance,
+    /// Escape time palette darkened near the boundary
+    Shaded,
+    /// Black boundary lines on white
+    LineArt,
+    /// Palette over the rank of the escape time among all pixels, for even contrast at any depth
+    Histogram,
+    /// Palette over how close each orbit comes to the --trap shape, or the color of the image trap
+    Trap,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum InteriorMode {
+    /// Plain black
+    Black,
+    /// Palette over the final |z|
+    Magnitude,
+    /// One color per period of the attracting cycle
+    Period,
+    /// Palette over the interior distance estimate
+    Distance,
+    /// Palette over the angle of the cycle multiplier, dark at the edge of each component
+    Multiplier,
+}
+
+impl From<InteriorMode> for Interior {
+    fn from(mode: InteriorMode) -> Interior {
+        match mode {
+            InteriorMode::Black => Interior::Black,
+            InteriorMode::Magnitude => Interior::Magnitude,
+            InteriorMode::Period => Interior::Period,
+            InteriorMode::Distance => Interior::Distance,
+            InteriorMode::Multiplier => Interior::Multiplier,
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct PaletteArgs {
+    /// Built-in color palette
+    #[arg(long, value_enum, default_value_t = PaletteName::Ultra)]
+    palette: PaletteName,
 
-    fn magnitude_squared(&self) -> f64 {
-        self.re * self.re + self.im * self.im
+    /// Custom gradient as "pos:#rrggbb,pos:#rrggbb,...", overrides --palette
+    #[arg(long)]
+    gradient: Option<Gradient>,
+
+    /// Shift along the palette, in cycles
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    palette_offset: f64,
+
+    /// Number of palette cycles per 64 iterations
+    #[arg(long, default_value_t = 1.0)]
+    palette_density: f64,
+
+    /// How the palette wraps past its last color
+    #[arg(long, value_enum, default_value_t = WrapMode::Repeat)]
+    palette_wrap: WrapMode,
+}
+
+impl PaletteArgs {
+    fn to_palette(&self) -> Palette {
+        let gradient = match &self.gradient {
+            Some(gradient) => gradient.clone(),
+            None => NamedGradient::from(self.palette).gradient(),
+        };
+        Palette {
+            gradient,
+            offset: self.palette_offset,
+            density: self.palette_density,
+            wrap: self.palette_wrap.into(),
+        }
     }
+}
 
-    fn square(&self) -> Complex {
-        Complex {
-            re: self.re * self.re - self.im * self.im,
-            im: 2.0 * self.re * self.im,
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum FractalKind {
+    Mandelbrot,
+    BurningShip,
+    Tricorn,
+    Multibrot,
+}
+
+impl FractalKind {
+    fn to_fractal(self, power: f64) -> Fractal {
+        match self {
+            FractalKind::Mandelbrot => Fractal::Mandelbrot,
+            FractalKind::BurningShip => Fractal::BurningShip,
+            FractalKind::Tricorn => Fractal::Tricorn,
+            FractalKind::Multibrot => Fractal::Multibrot { power },
         }
     }
 }
 
-impl Add for Complex {
-    type Output = Complex;
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum PrecisionMode {
+    /// f64 while it resolves the pixels, then double-double, then perturbation
+    Auto,
+    /// Plain f64, fastest
+    F64,
+    /// Double-double (about 106 bits), for pixel spacings down to about 1e-28
+    Dd,
+    /// Perturbation deep zoom, Mandelbrot only
+    Perturbation,
+}
 
-    fn add(self, other: Complex) -> Complex {
-        Complex {
-            re: self.re + other.re,
-            im: self.im + other.im,
+impl From<PrecisionMode> for Precision {
+    fn from(mode: PrecisionMode) -> Precision {
+        match mode {
+            PrecisionMode::Auto => Precision::Auto,
+            PrecisionMode::F64 => Precision::F64,
+            PrecisionMode::Dd => Precision::DoubleDouble,
+            PrecisionMode::Perturbation => Precision::Perturbation,
         }
     }
 }
 
-#[derive(Clone, Copy)]
-struct Color {
-    r: u8,
-    g: u8,
-    b: u8,
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum EasingMode {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl From<EasingMode> for Easing {
+    fn from(mode: EasingMode) -> Easing {
+        match mode {
+            EasingMode::Linear => Easing::Linear,
+            EasingMode::EaseIn => Easing::EaseIn,
+            EasingMode::EaseOut => Easing::EaseOut,
+            EasingMode::EaseInOut => Easing::EaseInOut,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum SamplingMode {
+    Grid,
+    Jitter,
+}
+
+impl From<SamplingMode> for Sampling {
+    fn from(mode: SamplingMode) -> Sampling {
+        match mode {
+            SamplingMode::Grid => Sampling::Grid,
+            SamplingMode::Jitter => Sampling::Jitter,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum PaletteName {
+    Ultra,
+    Classic,
+    Fire,
+    Ocean,
+    Grayscale,
+}
+
+impl From<PaletteName> for NamedGradient {
+    fn from(name: PaletteName) -> NamedGradient {
+        match name {
+            PaletteName::Ultra => NamedGradient::Ultra,
+            PaletteName::Classic => NamedGradient::Classic,
+            PaletteName::Fire => NamedGradient::Fire,
+            PaletteName::Ocean => NamedGradient::Ocean,
+            PaletteName::Grayscale => NamedGradient::Grayscale,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum WrapMode {
+    Repeat,
+    Mirror,
+    Clamp,
+}
+
+impl From<WrapMode> for Wrap {
+    fn from(mode: WrapMode) -> Wrap {
+        match mode {
+            WrapMode::Repeat => Wrap::Repeat,
+            WrapMode::Mirror => Wrap::Mirror,
+            WrapMode::Clamp => Wrap::Clamp,
+        }
+    }
 }
 
 fn main() -> std::io::Result<()> {
-    let args = Args::parse();
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
 
-    let img = generate_image(args.width, args.height, args.max_iter);
+    // Custom formulas are compiled here so their errors read like those of other flags
+    if let Some(image) = cli.image_args()
+        && let Err(e) = image.fractal()
+    {
+        Cli::command().error(ErrorKind::ValueValidation, format!("invalid --formula: {e}")).exit();
+    }
 
-    write_png(&args.output, args.width, args.height, &img)
+    match cli.command {
+        None => render(cli.render, &matches),
+        Some(Command::Recolor(args)) => recolor(args),
+        Some(Command::Animate(args)) => animate(args),
+        Some(Command::Cycle(args)) => cycle(args),
+        Some(Command::Buddhabrot(args)) => buddhabrot(args),
+        Some(Command::Newton(args)) => newton(args),
+    }
 }
 
-/// Generate a Mandelbrot image
-fn generate_image(width: usize, height: usize, max_iter: usize) -> Vec<Color> {
-    
+fn render(args: RenderArgs, matches: &ArgMatches) -> std::io::Result<()> {
+    let scene = match (&args.scene, &args.from_image) {
+        (Some(path), _) => args.override_scene(read_scene(path)?, matches)?,
+        (None, Some(path)) => {
+            // Never write over the source image or its data unless asked to
+            let mut scene = read_png_scene(path)?;
+            scene.output.file = args.output.clone();
+            scene.output.save_data = None;
+            args.override_scene(scene, matches)?
+        }
+        (None, None) => args.to_scene(),
+    };
+    match &args.dump_scene {
+        Some(path) if path.as_os_str() == "-" => print!("{}", scene.to_toml()),
+        Some(path) => write_scene(path, &scene)?,
+        None => {}
+    }
+
+    let mut params = scene.to_params();
+    let center = &scene.view.center;
+    let output = &scene.output;
+    // Saved data can be recolored with any interior, which needs every pixel iterated
+    params.subdivide &= output.save_data.is_none();
+
     // Progress bar setup
-    let total = (width * height) as u64;
-    let pb = ProgressBar::new(total);
-    pb.set_style(
-        ProgressStyle::with_template(
-            "{spinner} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({percent}%)"
-        ).unwrap()
-    );
-
-    (0..total)
-        .into_par_iter()
-        .progress_with(pb)
-        .map(|i| {
-            let x = (i % width as u64) as usize;
-            let y = (i / width as u64) as usize;
-            let c = map_screen_to_complex(x, y, width, height);
-            match mandelbrot(c, max_iter) {
-                None => Color { r: 0, g: 0, b: 0 },
-                Some(s) => color(s),
-            }
-        })
-        .collect()
+    let pb = ProgressBar::new(0);
+    pb.set_style(pixel_style());
+
+    if output.format == OutputFormat::PngStream {
+        if scene.fractal.precision.resolve(&params) != Precision::F64 {
+            return Err(std::io::Error::other("streaming output is only available with f64 precision"));
+        }
+        if output.save_data.is_some() || args.checkpoint.is_some() {
+            return Err(std::io::Error::other("streaming output cannot save escape data or checkpoints"));
+        }
+        check_formula(&params)?;
+        let text = scene.png_text();
+        let text: Vec<(&str, &str)> = text.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        return write_png_streaming(&output.file, &params, &text, pb);
+    }
+
+    let (data, img) = match &args.checkpoint {
+        Some(path) => render_resumable(&params, center, scene.fractal.precision, path, args.resume, pb)?,
+        None => render_image(&params, center, scene.fractal.precision, pb)?,
+    };
+
+    if let Some(path) = &output.save_data {
+        write_data(path, &data)?;
+    }
+
+    write_png_with_scene(&output.file, &img, &scene)?;
+    if let Some(path) = &args.checkpoint {
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
 }
 
-/// Map screen plane coordinates to complex plane coordinates
-fn map_screen_to_complex(x: usize, y: usize, width: usize, height: usize) -> Complex {
+fn recolor(args: RecolorArgs) -> std::io::Result<()> {
+    let data = read_data(&args.input)?;
+    let img = data.colorize(&args.palette.to_palette(), &args.coloring.to_coloring(), args.coloring.interior.into());
+    write_png(&args.output, &img)
+}
+
+fn animate(args: AnimateArgs) -> std::io::Result<()> {
+    let animation =
+        Animation::new(args.keyframes, args.frames, args.easing.into()).map_err(std::io::Error::other)?;
+    let mut frames = FrameWriter::new(&args.output)?;
+
+    // One bar for the frames, one for the pixels of the current frame
+    let bars = MultiProgress::new();
+    let overall = bars.add(ProgressBar::new(animation.frames as u64));
+    overall.set_style(frame_style());
 
-    let  x_interval = (-2.0, 1.0);
-    let  y_interval = (-1.5, 1.5);
+    for view in animation.views() {
+        let params = args.image.to_params(&view.center, view.zoom);
 
-    let re = (x as f64 / width as f64) * (x_interval.1 - x_interval.0) + x_interval.0;
-    let im = y_interval.1 - ((y as f64 + 0.5) / height as f64) * (y_interval.1 - y_interval.0);
+        let pb = bars.insert_after(&overall, ProgressBar::new(0));
+        pb.set_style(pixel_style());
 
-    Complex { re, im }
+        let (_, img) = render_image(&params, &view.center, args.image.precision(), pb.clone())?;
+        frames.push(img)?;
+
+        bars.remove(&pb);
+        overall.inc(1);
+    }
+
+    overall.finish();
+    frames.finish()
 }
 
-/// Compute the escape time for a point in the Mandelbrot set.
-fn mandelbrot(c: Complex, max_iter: usize) -> Option<f64>  {
+fn cycle(args: CycleArgs) -> std::io::Result<()> {
+    if args.frames == 0 {
+        return Err(std::io::Error::other("a palette cycle needs at least one frame"));
+    }
+    let mut params = args.image.to_params(&args.center, args.zoom);
+    let mut frames = FrameWriter::new(&args.output)?;
+
+    let pb = ProgressBar::new(0);
+    pb.set_style(pixel_style());
+    let (data, _) = render_image(&params, &args.center, args.image.precision(), pb)?;
 
-    // Generate the sequence z_{n+1} = z_n^2 + c, starting from z_0 = 0
-    // Stop if the magnitude of z exceeds 2 (i.e., magnitude_squared > 4)
-    let esc = successors(Some(Complex { re: 0.0, im: 0.0 }), move |&z| Some(z.square() + c))
-        .take(max_iter)                 // Limit the number of iterations
-        .enumerate()                 // Keep track of the iteration count
-        .find(|(_, z)| z.magnitude_squared() > 4.0);        // Escape condition
+    // Only the colors change between frames, so the escape data is reused
+    let overall = ProgressBar::new(args.frames as u64);
+    overall.set_style(frame_style());
+    let offset = params.palette.offset;
+    let histogram = data.histogram(&params.coloring);
 
+    for i in 0..args.frames {
+        params.palette.offset = offset + i as f64 / args.frames as f64;
+        let mut img = data.colorize(&params.palette, &params.coloring, params.interior);
+        supersample(&params, &params.fractal, &mut img, histogram.as_ref(), ProgressBar::hidden());
+        frames.push(img)?;
+        overall.inc(1);
+    }
+
+    overall.finish();
+    frames.finish()
+}
+
+fn buddhabrot(args: BuddhabrotArgs) -> std::io::Result<()> {
+    let params = BuddhabrotParams {
+        width: args.width,
+        height: args.height,
+        viewport: Viewport::new(args.center, args.zoom),
+        fractal: args.fractal.to_fractal(args.power),
+        max_iter: args.nebula.unwrap_or([args.max_iter; 3]),
+        min_iter: args.min_iter,
+        samples: args.samples,
+        anti: args.anti,
+        seed: args.seed,
+        gamma: args.gamma,
+    };
 
-    // Apply smoothing formula if the point escaped
-    // https://en.wikipedia.org/wiki/Plotting_algorithms_for_the_Mandelbrot_set#Continuous_(smooth)_coloring
-    esc.map(|(n, z)| {
-        let zn = z.magnitude();
-        let nu = (zn.ln()).ln() / 2.0_f64.ln(); // ln(ln(|z_n|))/ln(2)
-        (n as f64) + 1.0 - nu // Smooth iteration count
-    })
+    let pb = ProgressBar::new(0);
+    pb.set_style(pixel_style());
+
+    write_png(&args.output, &render_buddhabrot_with_progress(&params, pb))
 }
 
-/// Map the escape time to a color
-fn color(escape_time: f64) -> Color {
-    return Color {
-        r: (escape_time * 9.0) as u8,
-        g: (escape_time * 7.0) as u8,
-        b: (escape_time * 5.0) as u8,
+fn newton(args: NewtonArgs) -> std::io::Result<()> {
+    let polynomial = if args.roots.is_empty() { args.coefficients } else { Polynomial::from_roots(&args.roots) };
+    let params = NewtonParams {
+        width: args.width,
+        height: args.height,
+        viewport: Viewport::new(args.center, args.zoom),
+        polynomial,
+        max_iter: args.max_iter,
+        tolerance: args.tolerance,
+        shading: args.shading,
     };
+
+    let pb = ProgressBar::new(0);
+    pb.set_style(pixel_style());
+
+    write_png(&args.output, &render_newton_with_progress(&params, pb))
 }
 
-/// Write the image into a PNG file
-fn write_png(filename: &str, width: usize, height: usize, img: &[Color]) -> std::io::Result<()> {
-    let mut imgbuf = RgbImage::new(width as u32, height as u32);
+/// Sends finished animation frames to numbered PNG files and collects them for GIF or APNG encoding
+struct FrameWriter<'a> {
+    args: &'a AnimationOutputArgs,
+    dir: Option<PathBuf>,
+    count: usize,
+    frames: Vec<Image>,
+}
 
-    for (i, pixel) in img.iter().enumerate() {
-        let x = (i % width) as u32;
-        let y = (i / width) as u32;
-        imgbuf.put_pixel(x, y, Rgb([pixel.r, pixel.g, pixel.b]));
+impl<'a> FrameWriter<'a> {
+    fn new(args: &'a AnimationOutputArgs) -> std::io::Result<FrameWriter<'a>> {
+        let dir = args.frame_dir();
+        if let Some(dir) = &dir {
+            std::fs::create_dir_all(dir)?;
+        }
+        Ok(FrameWriter { args, dir, count: 0, frames: Vec::new() })
     }
 
-    imgbuf.save(filename).map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+    fn push(&mut self, img: Image) -> std::io::Result<()> {
+        self.count += 1;
+        if let Some(dir) = &self.dir {
+            write_png(dir.join(format!("frame_{:05}.png", self.count)), &img)?;
+        }
+        if self.args.collects_frames() {
+            self.frames.push(img);
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> std::io::Result<()> {
+        let options = self.args.to_options();
+        if let Some(path) = &self.args.gif {
+            write_gif(path, &self.frames, &options)?;
+        }
+        if let Some(path) = &self.args.apng {
+            write_apng(path, &self.frames, &options)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compute and color one image in the given precision, or the one the view needs
+fn render_image(
+    params: &RenderParams,
+    center: &DecimalComplex,
+    precision: Precision,
+    pb: ProgressBar,
+) -> std::io::Result<(EscapeData, Image)> {
+    let data = match choose_precision(params, precision)? {
+        Precision::Perturbation => {
+            compute_deep_with_progress(params, center, pb.clone()).map_err(std::io::Error::other)?
+        }
+        Precision::DoubleDouble => compute_double_double_with_progress(params, center, pb.clone()),
+        Precision::Auto | Precision::F64 => compute_with_progress(params, pb.clone()),
+    };
+
+    let mut img = data.colorize(&params.palette, &params.coloring, params.interior);
+    supersample(params, &params.fractal, &mut img, data.histogram(&params.coloring).as_ref(), pb);
+
+    Ok((data, img))
+}
+
+/// Like `render_image`, saving progress to a checkpoint file; Ctrl-C stops after saving it
+fn render_resumable(
+    params: &RenderParams,
+    center: &DecimalComplex,
+    precision: Precision,
+    checkpoint: &Path,
+    resume: bool,
+    pb: ProgressBar,
+) -> std::io::Result<(EscapeData, Image)> {
+    let precision = choose_precision(params, precision)?;
+    catch_interrupt();
+
+    let data = compute_resumable(params, center, precision, checkpoint, resume, &INTERRUPTED, pb.clone()).map_err(|e| {
+        if e.kind() == std::io::ErrorKind::Interrupted {
+            pb.abandon();
+            let msg = format!("interrupted; continue with --checkpoint {} --resume", checkpoint.display());
+            std::io::Error::new(std::io::ErrorKind::Interrupted, msg)
+        } else {
+            e
+        }
+    })?;
+
+    let mut img = data.colorize(&params.palette, &params.coloring, params.interior);
+    supersample(params, &params.fractal, &mut img, data.histogram(&params.coloring).as_ref(), pb);
+
+    Ok((data, img))
+}
+
+/// Set by the first Ctrl-C while a checkpointed render runs
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Turn the first Ctrl-C into a request to stop at the next safe point; a second one exits at once
+#[cfg(unix)]
+fn catch_interrupt() {
+    extern "C" fn on_interrupt(_: libc::c_int) {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+        // Only async-signal-safe calls are allowed here
+        unsafe { libc::signal(libc::SIGINT, libc::SIG_DFL) };
+    }
+
+    // The handler only touches an atomic and restores the default action
+    unsafe { libc::signal(libc::SIGINT, on_interrupt as *const () as libc::sighandler_t) };
+}
+
+#[cfg(not(unix))]
+fn catch_interrupt() {}
+
+/// Precision the view is rendered with, rejecting settings that cannot be combined with it
+fn choose_precision(params: &RenderParams, precision: Precision) -> std::io::Result<Precision> {
+    check_formula(params)?;
+
+    let precision = precision.resolve(params);
+    if precision != Precision::F64 && params.antialias.samples > 1 {
+        return Err(std::io::Error::other("supersampling is only available with f64 precision"));
+    }
+    Ok(precision)
+}
+
+/// Reject settings the chosen formula cannot render
+fn check_formula(params: &RenderParams) -> std::io::Result<()> {
+    if params.coloring.needs_distance() {
+        match params.fractal {
+            Fractal::BurningShip | Fractal::Tricorn => {
+                return Err(std::io::Error::other("distance estimation needs a holomorphic formula"));
+            }
+            Fractal::Custom(_) => return Err(std::io::Error::other("distance estimation is not available with --formula")),
+            _ => {}
+        }
+    }
+    if params.coloring == Coloring::Trap && params.trap.is_none() {
+        return Err(std::io::Error::other("the trap coloring needs an orbit trap, set with --trap"));
+    }
+    Ok(())
+}
+
+fn frame_style() -> ProgressStyle {
+    ProgressStyle::with_template("frames [{elapsed_precise}] [{bar:40.green/blue}] {pos}/{len} (eta {eta})").unwrap()
+}
+
+fn pixel_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{spinner} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({percent}%)"
+    ).unwrap()
 }
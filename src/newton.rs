@@ -0,0 +1,265 @@
+//! Newton fractals.
+//!
+//! Every pixel is a starting point z_0 of Newton's method for a polynomial p,
+//!
+//! z_{n+1} = z_n - p(z_n) / p'(z_n)
+//!
+//! and is colored by the root its iteration converges to, darker the more
+//! steps it needed. Points that do not converge within the iteration limit are
+//! black.
+//! https://en.wikipedia.org/wiki/Newton_fractal
+
+use std::str::FromStr;
+
+use indicatif::{ParallelProgressIterator, ProgressBar};
+use rayon::prelude::*;
+
+use crate::color::{Color, Oklab};
+use crate::complex::Complex;
+use crate::image::Image;
+use crate::viewport::{map_screen_to_complex, Viewport};
+
+/// Iterations of the Durand-Kerner root finder
+const ROOT_ITERATIONS: usize = 1000;
+
+/// A converged point belongs to a root closer than this
+const ROOT_TOLERANCE: f64 = 1e-6;
+
+/// Lightness and chroma of the root colors in Oklab
+const ROOT_LIGHTNESS: f64 = 0.75;
+const ROOT_CHROMA: f64 = 0.13;
+
+/// A polynomial with complex coefficients
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polynomial {
+    /// Coefficients from the highest degree down to the constant term, the first one non-zero
+    coefficients: Vec<Complex>,
+}
+
+impl Polynomial {
+    /// Polynomial with the given coefficients, highest degree first
+    pub fn new(mut coefficients: Vec<Complex>) -> Result<Polynomial, String> {
+        let leading = coefficients.iter().take_while(|c| **c == Complex::ZERO).count();
+        coefficients.drain(..leading);
+        if coefficients.len() < 2 {
+            return Err("the polynomial needs a degree of at least 1".to_string());
+        }
+        Ok(Polynomial { coefficients })
+    }
+
+    /// The monic polynomial (z - r_1)(z - r_2)... with the given roots
+    pub fn from_roots(roots: &[Complex]) -> Polynomial {
+        let mut coefficients = vec![Complex::ONE];
+        for &root in roots {
+            // Multiply by (z - root)
+            coefficients.push(Complex::ZERO);
+            for i in (1..coefficients.len()).rev() {
+                coefficients[i] = coefficients[i] - coefficients[i - 1] * root;
+            }
+        }
+        Polynomial { coefficients }
+    }
+
+    pub fn coefficients(&self) -> &[Complex] {
+        &self.coefficients
+    }
+
+    pub fn degree(&self) -> usize {
+        self.coefficients.len() - 1
+    }
+
+    /// p(z) and p'(z), by Horner's scheme
+    pub fn eval(&self, z: Complex) -> (Complex, Complex) {
+        let mut p = Complex::ZERO;
+        let mut dp = Complex::ZERO;
+        for &c in &self.coefficients {
+            dp = dp * z + p;
+            p = p * z + c;
+        }
+        (p, dp)
+    }
+
+    /// All complex roots, with multiplicity, by the Durand-Kerner method
+    /// https://en.wikipedia.org/wiki/Durand%E2%80%93Kerner_method
+    pub fn roots(&self) -> Vec<Complex> {
+        let lead = self.coefficients[0];
+        let monic = Polynomial { coefficients: self.coefficients.iter().map(|&c| c / lead).collect() };
+
+        // Starting points on a spiral, neither real nor roots of unity
+        let seed = Complex::new(0.4, 0.9);
+        let mut roots: Vec<Complex> = (0..self.degree()).map(|k| seed.powi(k as i32)).collect();
+
+        for _ in 0..ROOT_ITERATIONS {
+            let mut moved = 0.0f64;
+            for i in 0..roots.len() {
+                let denominator = (0..roots.len())
+                    .filter(|&j| j != i)
+                    .fold(Complex::ONE, |acc, j| acc * (roots[i] - roots[j]));
+                let step = monic.eval(roots[i]).0 / denominator;
+                if step.re.is_finite() && step.im.is_finite() {
+                    roots[i] = roots[i] - step;
+                    moved = moved.max(step.magnitude());
+                }
+            }
+            if moved < 1e-15 {
+                break;
+            }
+        }
+        roots
+    }
+}
+
+impl FromStr for Polynomial {
+    type Err = String;
+
+    /// Parse coefficients from the highest degree down, separated by spaces or ';',
+    /// each a real number or "re,im"; "1;0;0;-1" is z^3 - 1
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let coefficients = s
+            .split([';', ' '])
+            .filter(|term| !term.is_empty())
+            .map(|term| {
+                if term.contains(',') {
+                    term.parse()
+                } else {
+                    term.parse().map(|re| Complex::new(re, 0.0)).map_err(|_| format!("invalid coefficient \"{term}\""))
+                }
+            })
+            .collect::<Result<Vec<Complex>, String>>()?;
+        Polynomial::new(coefficients)
+    }
+}
+
+impl std::fmt::Display for Polynomial {
+    /// Written in the form accepted by `FromStr`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, c) in self.coefficients.iter().enumerate() {
+            let sep = if i == 0 { "" } else { ";" };
+            write!(f, "{sep}{c}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Everything needed to render a Newton fractal
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewtonParams {
+    /// Image width in pixels
+    pub width: usize,
+    /// Image height in pixels
+    pub height: usize,
+    /// Region of the complex plane to render
+    pub viewport: Viewport,
+    /// Polynomial whose roots attract the iteration
+    pub polynomial: Polynomial,
+    /// Maximum number of Newton steps
+    pub max_iter: usize,
+    /// The iteration has converged once a step is shorter than this
+    pub tolerance: f64,
+    /// Darkening per step: colors are scaled by exp(-shading * steps)
+    pub shading: f64,
+}
+
+impl Default for NewtonParams {
+    fn default() -> NewtonParams {
+        NewtonParams {
+            width: 1000,
+            height: 1000,
+            viewport: Viewport::new(Complex::ZERO, 1.0),
+            polynomial: Polynomial::from_roots(&[
+                Complex::new(1.0, 0.0),
+                Complex::new(-0.5, 0.75f64.sqrt()),
+                Complex::new(-0.5, -(0.75f64.sqrt())),
+            ]),
+            max_iter: 100,
+            tolerance: 1e-10,
+            shading: 0.08,
+        }
+    }
+}
+
+/// Where the iteration of one starting point ended
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Convergence {
+    /// Last value of the iteration
+    pub z: Complex,
+    /// Smooth number of steps until convergence, `None` if it did not converge
+    pub steps: Option<f64>,
+}
+
+/// Iterate Newton's method from `z0`
+pub fn newton(polynomial: &Polynomial, z0: Complex, max_iter: usize, tolerance: f64) -> Convergence {
+    let mut z = z0;
+    // ln of the previous step length, for the smooth step count
+    let mut last = f64::INFINITY;
+
+    for n in 0..max_iter {
+        let (p, dp) = polynomial.eval(z);
+        let step = p / dp;
+        let length = step.magnitude();
+        if !length.is_finite() {
+            // Critical point of p: the tangent never crosses zero
+            break;
+        }
+        z = z - step;
+
+        if length < tolerance {
+            // Newton's method converges quadratically, so ln|step| doubles every
+            // step; the fraction is where it crossed ln(tolerance)
+            let fraction =
+                if last.is_finite() && last < 0.0 { (tolerance.ln() / last).log2().clamp(0.0, 1.0) } else { 1.0 };
+            return Convergence { z, steps: Some(n as f64 + fraction) };
+        }
+        last = length.ln();
+    }
+    Convergence { z, steps: None }
+}
+
+/// `count` evenly spaced hues of equal lightness, one per root
+pub fn root_colors(count: usize) -> Vec<Color> {
+    (0..count)
+        .map(|i| {
+            let hue = std::f64::consts::TAU * i as f64 / count as f64;
+            Oklab { l: ROOT_LIGHTNESS, a: ROOT_CHROMA * hue.cos(), b: ROOT_CHROMA * hue.sin() }.to_color()
+        })
+        .collect()
+}
+
+/// Render a Newton fractal, reporting one tick per pixel on `pb`
+pub fn render_newton_with_progress(params: &NewtonParams, pb: ProgressBar) -> Image {
+    let NewtonParams { width, height, viewport, ref polynomial, max_iter, tolerance, shading } = *params;
+    let roots = polynomial.roots();
+    let colors = root_colors(roots.len());
+
+    let total = (width * height) as u64;
+    pb.set_length(total);
+
+    let pixels = (0..total)
+        .into_par_iter()
+        .progress_with(pb)
+        .map(|i| {
+            let x = (i % width as u64) as usize;
+            let y = (i / width as u64) as usize;
+            let result = newton(polynomial, map_screen_to_complex(x, y, width, height, &viewport), max_iter, tolerance);
+
+            let Some(steps) = result.steps else {
+                return Color::BLACK;
+            };
+            let nearest = roots
+                .iter()
+                .map(|&root| (result.z - root).magnitude())
+                .enumerate()
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+            match nearest {
+                Some((k, distance)) if distance < ROOT_TOLERANCE => {
+                    let shade = (-shading * steps).exp();
+                    let [r, g, b] = colors[k].to_linear();
+                    Color::from_linear([r * shade, g * shade, b * shade])
+                }
+                _ => Color::BLACK,
+            }
+        })
+        .collect();
+
+    Image { width, height, pixels }
+}
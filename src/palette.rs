@@ -0,0 +1,243 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::color::{Color, Oklab};
+
+/// A color at a position in [0, 1] along a gradient
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorStop {
+    pub position: f64,
+    pub color: Color,
+}
+
+/// A color gradient interpolated in the Oklab color space
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gradient {
+    stops: Vec<ColorStop>,
+    lab: Vec<Oklab>,
+}
+
+impl Gradient {
+    /// Build a gradient from at least one stop; stops are sorted by position
+    pub fn new(mut stops: Vec<ColorStop>) -> Result<Gradient, String> {
+        if stops.is_empty() {
+            return Err("a gradient needs at least one color stop".to_string());
+        }
+        if let Some(stop) = stops.iter().find(|s| !(0.0..=1.0).contains(&s.position)) {
+            return Err(format!("stop position {} is outside [0, 1]", stop.position));
+        }
+        stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+        let lab = stops.iter().map(|s| s.color.to_oklab()).collect();
+        Ok(Gradient { stops, lab })
+    }
+
+    /// Build a gradient from colors spaced evenly over [0, 1]
+    pub fn evenly_spaced(colors: &[Color]) -> Gradient {
+        let last = colors.len().saturating_sub(1).max(1) as f64;
+        let stops = colors
+            .iter()
+            .enumerate()
+            .map(|(i, &color)| ColorStop { position: i as f64 / last, color })
+            .collect();
+        Gradient::new(stops).expect("built-in gradient is valid")
+    }
+
+    pub fn stops(&self) -> &[ColorStop] {
+        &self.stops
+    }
+
+    /// Sample the gradient at `t` in [0, 1]
+    pub fn sample(&self, t: f64) -> Color {
+        let i = self.stops.partition_point(|s| s.position <= t);
+        if i == 0 {
+            return self.stops[0].color;
+        }
+        if i == self.stops.len() {
+            return self.stops[i - 1].color;
+        }
+
+        let (a, b) = (&self.stops[i - 1], &self.stops[i]);
+        let f = (t - a.position) / (b.position - a.position);
+        self.lab[i - 1].lerp(self.lab[i], f).to_color()
+    }
+}
+
+impl FromStr for Gradient {
+    type Err = String;
+
+    /// Parse a gradient written as "pos:#rrggbb,pos:#rrggbb,..."
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let stops = s
+            .split(',')
+            .map(|stop| {
+                let (position, color) = stop
+                    .split_once(':')
+                    .ok_or_else(|| format!("expected \"pos:#rrggbb\", got \"{stop}\""))?;
+                let position = position
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|e| format!("invalid stop position \"{position}\": {e}"))?;
+                Ok(ColorStop { position, color: color.parse()? })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Gradient::new(stops)
+    }
+}
+
+impl std::fmt::Display for Gradient {
+    /// Written as "pos:#rrggbb,pos:#rrggbb,...", the form accepted by `FromStr`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, stop) in self.stops.iter().enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            write!(f, "{separator}{}:{}", stop.position, stop.color)?;
+        }
+        Ok(())
+    }
+}
+
+/// How escape times beyond one palette cycle are mapped back onto the gradient
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Wrap {
+    /// Start over from the beginning of the gradient
+    #[default]
+    Repeat,
+    /// Run back and forth through the gradient
+    Mirror,
+    /// Stay at the last color
+    Clamp,
+}
+
+/// Built-in gradients
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NamedGradient {
+    #[default]
+    Ultra,
+    Classic,
+    Fire,
+    Ocean,
+    Grayscale,
+}
+
+impl NamedGradient {
+    pub fn gradient(self) -> Gradient {
+        match self {
+            // The Ultra Fractal default gradient
+            NamedGradient::Ultra => Gradient::new(vec![
+                ColorStop { position: 0.0, color: Color::new(0, 7, 100) },
+                ColorStop { position: 0.16, color: Color::new(32, 107, 203) },
+                ColorStop { position: 0.42, color: Color::new(237, 255, 255) },
+                ColorStop { position: 0.6425, color: Color::new(255, 170, 0) },
+                ColorStop { position: 0.8575, color: Color::new(0, 2, 0) },
+                ColorStop { position: 1.0, color: Color::new(0, 7, 100) },
+            ])
+            .expect("built-in gradient is valid"),
+            // Warm glow similar to the original 9/7/5 multipliers
+            NamedGradient::Classic => Gradient::evenly_spaced(&[
+                Color::BLACK,
+                Color::new(90, 70, 50),
+                Color::new(255, 230, 190),
+                Color::WHITE,
+            ]),
+            NamedGradient::Fire => Gradient::evenly_spaced(&[
+                Color::BLACK,
+                Color::new(128, 0, 0),
+                Color::new(255, 96, 0),
+                Color::new(255, 220, 64),
+                Color::WHITE,
+            ]),
+            NamedGradient::Ocean => Gradient::evenly_spaced(&[
+                Color::new(0, 8, 32),
+                Color::new(0, 64, 128),
+                Color::new(0, 160, 192),
+                Color::new(224, 255, 255),
+            ]),
+            NamedGradient::Grayscale => Gradient::evenly_spaced(&[Color::BLACK, Color::WHITE]),
+        }
+    }
+}
+
+/// Maps smooth escape times to colors through a gradient
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    pub gradient: Gradient,
+    /// Shift along the gradient, in cycles
+    pub offset: f64,
+    /// Number of gradient cycles per `Palette::PERIOD` iterations
+    pub density: f64,
+    pub wrap: Wrap,
+}
+
+impl Palette {
+    /// Escape time spanned by one gradient cycle at density 1
+    pub const PERIOD: f64 = 64.0;
+
+    pub fn new(gradient: Gradient) -> Palette {
+        Palette { gradient, offset: 0.0, density: 1.0, wrap: Wrap::default() }
+    }
+
+    /// Map the escape time to a color
+    pub fn color(&self, escape_time: f64) -> Color {
+        let t = self.offset + escape_time * self.density / Self::PERIOD;
+        let t = match self.wrap {
+            Wrap::Repeat => t.rem_euclid(1.0),
+            Wrap::Mirror => 1.0 - (t.rem_euclid(2.0) - 1.0).abs(),
+            Wrap::Clamp => t.clamp(0.0, 1.0),
+        };
+        self.gradient.sample(t)
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::new(NamedGradient::default().gradient())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_gradient_stops() {
+        let gradient: Gradient = "1:#ffffff, 0.25:#ff8000,0:#000000".parse().unwrap();
+        let stops: Vec<_> = gradient.stops().iter().map(|s| (s.position, s.color)).collect();
+        assert_eq!(stops, [(0.0, Color::BLACK), (0.25, Color::new(255, 128, 0)), (1.0, Color::WHITE)]);
+        assert_eq!(gradient.to_string(), "0:#000000,0.25:#ff8000,1:#ffffff");
+        assert_eq!(gradient.to_string().parse(), Ok(gradient));
+    }
+
+    #[test]
+    fn rejects_bad_gradients() {
+        for s in ["", "0.5", "1.5:#ffffff", "-0.1:#ffffff", "x:#000000", "0:#fff", "0:#000000,1:#+f+f+f"] {
+            assert!(s.parse::<Gradient>().is_err(), "{s:?}");
+        }
+    }
+
+    #[test]
+    fn samples_stop_colors() {
+        let gradient: Gradient = "0:#000000,0.5:#ff8000,1:#ffffff".parse().unwrap();
+        assert_eq!(gradient.sample(-1.0), Color::BLACK);
+        assert_eq!(gradient.sample(0.5), Color::new(255, 128, 0));
+        assert_eq!(gradient.sample(1.0), Color::WHITE);
+        assert_eq!(gradient.sample(2.0), Color::WHITE);
+    }
+
+    #[test]
+    fn wrap_modes() {
+        let palette = |wrap| Palette { wrap, ..Palette::new(NamedGradient::Grayscale.gradient()) };
+        let at = |t: f64| palette(Wrap::Clamp).color(t * Palette::PERIOD);
+
+        // One and a quarter cycles
+        let escape_time = 1.25 * Palette::PERIOD;
+        assert_eq!(palette(Wrap::Repeat).color(escape_time), at(0.25));
+        assert_eq!(palette(Wrap::Mirror).color(escape_time), at(0.75));
+        assert_eq!(palette(Wrap::Clamp).color(escape_time), Color::WHITE);
+
+        // The offset is in cycles
+        let shifted = Palette { offset: 0.5, ..palette(Wrap::Repeat) };
+        assert_eq!(shifted.color(0.25 * Palette::PERIOD), at(0.75));
+    }
+}
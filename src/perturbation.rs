@@ -0,0 +1,344 @@
+//! Deep zoom rendering with perturbation theory.
+//!
+//! One reference orbit Z_n is computed in arbitrary precision at a point of the
+//! view; every pixel then only tracks its difference d_n = z_n - Z_n in `f64`:
+//!
+//! d_{n+1} = 2 Z_n d_n + d_n^2 + dc
+//!
+//! Where that difference becomes too large relative to the orbit the pixel is
+//! glitched (Pauldelbrot's criterion) and is recomputed against a secondary
+//! reference orbit taken inside the glitch.
+//! https://en.wikipedia.org/wiki/Plotting_algorithms_for_the_Mandelbrot_set#Perturbation_theory_and_series_approximation
+
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use indicatif::ProgressBar;
+use rayon::prelude::*;
+
+use crate::complex::Complex;
+use crate::data::EscapeData;
+use crate::escape::{distance_estimate, Plane, Sample};
+use crate::fixed::{DecimalComplex, Fixed};
+use crate::formula::{Fractal, Mandelbrot};
+use crate::image::Image;
+use crate::render::RenderParams;
+use crate::trap::{OrbitTrap, TrapTracker};
+
+/// Pixels with |z_n|^2 < GLITCH_TOLERANCE * |Z_n|^2 have lost their precision
+const GLITCH_TOLERANCE: f64 = 1e-6;
+
+/// Upper bound on the number of secondary reference orbits per image
+const MAX_REFERENCES: usize = 256;
+
+/// Extra fractional bits beyond the pixel spacing kept in the reference orbit
+const GUARD_BITS: u32 = 64;
+
+/// Result of iterating one pixel against a reference orbit
+#[derive(Debug, Clone, Copy)]
+enum Outcome {
+    /// Escaped or stayed bounded
+    Done(Sample),
+    /// Precision was lost; holds |z_n|^2 at that point, small near the glitch center
+    Glitch(f64),
+}
+
+/// Settings shared by every pixel of a deep render
+struct PixelSettings<'a> {
+    /// View center rounded to `f64`
+    center: Complex,
+    julia: Option<Complex>,
+    max_iter: usize,
+    /// Whether to compute the exterior distance estimate
+    distance: bool,
+    trap: Option<&'a OrbitTrap>,
+}
+
+/// A reference orbit Z_0, Z_1, ... rounded to `f64`, up to and including its escape
+struct ReferenceOrbit {
+    /// Offset of the reference point from the view center
+    offset: Complex,
+    orbit: Vec<Complex>,
+}
+
+impl ReferenceOrbit {
+    /// Compute the orbit of `center + offset` in fixed point arithmetic
+    fn compute(center: &DecimalComplex, offset: Complex, julia: Option<Complex>, max_iter: usize, frac_bits: u32) -> ReferenceOrbit {
+        let (re, im) = center.to_fixed(frac_bits);
+        let point = (
+            &re + &Fixed::from_f64(offset.re, frac_bits),
+            &im + &Fixed::from_f64(offset.im, frac_bits),
+        );
+
+        // Mandelbrot: z_0 = 0 and c = point; Julia: z_0 = point and c is fixed
+        let (mut z, c) = match julia {
+            None => ((Fixed::zero(frac_bits), Fixed::zero(frac_bits)), point),
+            Some(k) => (point, (Fixed::from_f64(k.re, frac_bits), Fixed::from_f64(k.im, frac_bits))),
+        };
+
+        let mut orbit = Vec::with_capacity(max_iter.min(1 << 20));
+        for _ in 0..max_iter {
+            let zf = Complex::new(z.0.to_f64(), z.1.to_f64());
+            orbit.push(zf);
+            if zf.magnitude_squared() > 4.0 {
+                break;
+            }
+
+            // z^2 + c = (re^2 - im^2 + c.re) + i(2 re im + c.im)
+            let re_im = &z.0 * &z.1;
+            let re = &(&z.0.square() - &z.1.square()) + &c.0;
+            let im = &(&re_im + &re_im) + &c.1;
+            z = (re, im);
+        }
+
+        ReferenceOrbit { offset, orbit }
+    }
+
+    /// Iterate a pixel at `offset` from the view center against this reference
+    fn iterate(&self, offset: Complex, settings: &PixelSettings, detect_glitches: bool) -> Outcome {
+        let &PixelSettings { center, julia, max_iter, distance, trap } = settings;
+        let plane = if julia.is_some() { Plane::Dynamic } else { Plane::Parameter };
+
+        let delta = Complex::new(offset.re - self.offset.re, offset.im - self.offset.im);
+        let (mut d, dc) = match plane {
+            Plane::Dynamic => (delta, Complex::ZERO),
+            Plane::Parameter => (Complex::ZERO, delta),
+        };
+        let mut z = Complex::ZERO;
+        let mut tracker = trap.map(TrapTracker::new);
+
+        // Derivative of the full orbit z_n = Z_n + d_n, as in `escape::iterate_point`
+        let mut dz = match plane {
+            Plane::Dynamic => Complex::ONE,
+            Plane::Parameter => Complex::ZERO,
+        };
+
+        for (n, &zr) in self.orbit.iter().enumerate().take(max_iter) {
+            z = zr + d;
+            let mag = z.magnitude_squared();
+
+            if mag > 4.0 {
+                // Same smoothing as `escape_time`
+                let nu = (z.magnitude().ln()).ln() / 2.0_f64.ln();
+                let distance = distance.then(|| {
+                    // Past the escape radius the orbit no longer needs the extra precision
+                    let c = julia.unwrap_or(Complex::new(center.re + offset.re, center.im + offset.im));
+                    distance_estimate(&Mandelbrot, z, dz, c, plane)
+                });
+                return Outcome::Done(Sample {
+                    escape_time: Some((n as f64) + 1.0 - nu),
+                    z,
+                    period: None,
+                    distance: distance.flatten(),
+                    multiplier: None,
+                    trap: tracker.and_then(TrapTracker::finish),
+                });
+            }
+            if detect_glitches && mag < GLITCH_TOLERANCE * zr.magnitude_squared() {
+                return Outcome::Glitch(mag);
+            }
+            if let (true, Some(tracker)) = (n > 0, &mut tracker) {
+                tracker.visit(z);
+            }
+
+            if distance {
+                dz = z * 2.0 * dz;
+                if plane == Plane::Parameter {
+                    dz = dz + Complex::ONE;
+                }
+            }
+
+            // d_{n+1} = 2 Z_n d_n + d_n^2 + dc
+            let two_zr = Complex::new(2.0 * zr.re, 2.0 * zr.im);
+            d = two_zr * d + d.square() + dc;
+        }
+
+        if self.orbit.len() < max_iter && detect_glitches {
+            // The reference escaped before this pixel did
+            Outcome::Glitch(f64::INFINITY)
+        } else {
+            let trap = tracker.and_then(TrapTracker::finish);
+            Outcome::Done(Sample { escape_time: None, z, period: None, distance: None, multiplier: None, trap })
+        }
+    }
+}
+
+/// Fractional bits needed to resolve the pixel spacing of the view
+pub fn required_bits(params: &RenderParams) -> u32 {
+    let spacing = params.viewport.pixel_spacing(params.width, params.height);
+    (-spacing.log2()).max(0.0).ceil() as u32 + GUARD_BITS
+}
+
+/// Whether perturbation can render `fractal`: the Mandelbrot formula and its Julia sets
+pub(crate) fn supports(fractal: &Fractal) -> bool {
+    matches!(fractal, Fractal::Mandelbrot)
+}
+
+/// Render an image with perturbation around the exact view `center`
+///
+/// Only the Mandelbrot formula (and its Julia sets) is supported; the center of
+/// `params.viewport` is ignored in favor of `center`.
+pub fn render_deep_with_progress(params: &RenderParams, center: &DecimalComplex, pb: ProgressBar) -> Result<Image, String> {
+    Ok(compute_deep_with_progress(params, center, pb)?.colorize(&params.palette, &params.coloring, params.interior))
+}
+
+/// Compute the raw escape data of every pixel with perturbation around the exact view `center`
+pub fn compute_deep_with_progress(params: &RenderParams, center: &DecimalComplex, pb: ProgressBar) -> Result<EscapeData, String> {
+    let &RenderParams { width, height, max_iter, .. } = params;
+    pb.set_length((width * height) as u64);
+
+    let never = AtomicBool::new(false);
+    let samples = compute_deep_rows(params, center, 0..height, &never, &pb)?.expect("never cancelled");
+    pb.finish();
+
+    let pixel_spacing = params.viewport.pixel_spacing(width, height);
+    Ok(EscapeData { width, height, max_iter, pixel_spacing, samples })
+}
+
+/// Samples of the pixels in `rows`, ticking `pb` once per pixel; `None` when
+/// `cancel` was set before they were all done
+pub(crate) fn compute_deep_rows(
+    params: &RenderParams,
+    center: &DecimalComplex,
+    rows: Range<usize>,
+    cancel: &AtomicBool,
+    pb: &ProgressBar,
+) -> Result<Option<Vec<Sample>>, String> {
+    match params.fractal {
+        Fractal::Mandelbrot => {}
+        Fractal::Custom(ref custom) => {
+            return Err(format!("deep zoom only supports the Mandelbrot formula, not \"{}\"", custom.source()));
+        }
+        ref fractal => return Err(format!("deep zoom only supports the Mandelbrot formula, not {fractal:?}")),
+    }
+
+    let &RenderParams { width, height, julia, max_iter, coloring, ref trap, .. } = params;
+    let frac_bits = required_bits(params);
+    let spacing = params.viewport.pixel_spacing(width, height);
+    let settings = PixelSettings {
+        center: center.to_complex(),
+        julia,
+        max_iter,
+        distance: coloring.needs_distance(),
+        trap: trap.as_ref(),
+    };
+    let first = rows.start * width;
+
+    // Offset of each pixel from the view center
+    let offset = |i: usize| {
+        let x = (i % width) as f64;
+        let y = (i / width) as f64;
+        Complex::new(
+            (x + 0.5 - width as f64 / 2.0) * spacing,
+            -(y + 0.5 - height as f64 / 2.0) * spacing,
+        )
+    };
+
+    // Indices are relative to the first pixel of `rows`
+    let mut outcomes = vec![Outcome::Glitch(f64::INFINITY); rows.len() * width];
+    let mut pending: Vec<usize> = (0..outcomes.len()).collect();
+    let mut reference_offset = Complex::ZERO;
+
+    for pass in 0..MAX_REFERENCES {
+        let reference = ReferenceOrbit::compute(center, reference_offset, julia, max_iter, frac_bits);
+        let last_pass = pass + 1 == MAX_REFERENCES;
+
+        let results: Vec<Outcome> = pending
+            .par_iter()
+            .map(|&i| {
+                if cancel.load(Ordering::Relaxed) {
+                    return Outcome::Glitch(f64::INFINITY);
+                }
+                let outcome = reference.iterate(offset(first + i), &settings, !last_pass);
+                if !matches!(outcome, Outcome::Glitch(_)) {
+                    pb.inc(1);
+                }
+                outcome
+            })
+            .collect();
+
+        if cancel.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+        for (&i, outcome) in pending.iter().zip(results) {
+            outcomes[i] = outcome;
+        }
+        pending.retain(|&i| matches!(outcomes[i], Outcome::Glitch(_)));
+
+        // The next reference is the glitched pixel closest to the glitch center
+        let next = pending.iter().min_by(|&&a, &&b| glitch_depth(outcomes[a]).total_cmp(&glitch_depth(outcomes[b])));
+        match next {
+            Some(&i) => reference_offset = offset(first + i),
+            None => break,
+        }
+    }
+
+    let samples = outcomes
+        .into_iter()
+        .map(|outcome| match outcome {
+            Outcome::Done(sample) => sample,
+            Outcome::Glitch(_) => Sample {
+                escape_time: None,
+                z: Complex::ZERO,
+                period: None,
+                distance: None,
+                multiplier: None,
+                trap: None,
+            },
+        })
+        .collect();
+
+    Ok(Some(samples))
+}
+
+fn glitch_depth(outcome: Outcome) -> f64 {
+    match outcome {
+        Outcome::Glitch(mag) => mag,
+        _ => f64::INFINITY,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::compute_double_double_with_progress;
+    use crate::viewport::Viewport;
+
+    /// A view near a minibrot where the center reference leaves part of the image glitched
+    fn glitched_view() -> (RenderParams, DecimalComplex) {
+        let center: DecimalComplex = "-1.768778833,-0.001738996".parse().unwrap();
+        let viewport = Viewport::new(center.to_complex(), 1e10);
+        (RenderParams { width: 48, height: 32, max_iter: 3000, viewport, ..RenderParams::default() }, center)
+    }
+
+    #[test]
+    fn center_reference_glitches() {
+        let (params, center) = glitched_view();
+        let spacing = params.viewport.pixel_spacing(params.width, params.height);
+        let settings =
+            PixelSettings { center: center.to_complex(), julia: None, max_iter: 3000, distance: false, trap: None };
+        let reference = ReferenceOrbit::compute(&center, Complex::ZERO, None, 3000, required_bits(&params));
+
+        let glitched = (0..params.width * params.height)
+            .filter(|&i| {
+                let x = (i % params.width) as f64 + 0.5 - params.width as f64 / 2.0;
+                let y = (i / params.width) as f64 + 0.5 - params.height as f64 / 2.0;
+                let offset = Complex::new(x * spacing, -y * spacing);
+                matches!(reference.iterate(offset, &settings, true), Outcome::Glitch(_))
+            })
+            .count();
+        assert!(glitched > 100, "{glitched} glitched pixels");
+    }
+
+    #[test]
+    fn secondary_references_match_double_double() {
+        let (params, center) = glitched_view();
+        let deep = compute_deep_with_progress(&params, &center, ProgressBar::hidden()).unwrap();
+        let exact = compute_double_double_with_progress(&params, &center, ProgressBar::hidden());
+
+        for (i, (a, b)) in deep.samples.iter().zip(&exact.samples).enumerate() {
+            let (a, b) = (a.escape_time.expect("escapes"), b.escape_time.expect("escapes"));
+            assert!((a - b).abs() < 1e-9, "pixel {i}: {a} vs {b}");
+        }
+    }
+}
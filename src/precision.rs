@@ -0,0 +1,103 @@
+//! Choice of the arithmetic the orbits are computed in.
+//!
+//! Plain `f64` coordinates stop telling pixels apart around a pixel spacing of
+//! 1e-13; double-double numbers reach about 1e-28 at a few times the cost, and
+//! perturbation deep zoom goes past that.
+
+use serde::{Deserialize, Serialize};
+
+use crate::perturbation;
+use crate::render::RenderParams;
+
+/// Relative precision of double-double numbers, 2^-104
+const DOUBLE_DOUBLE_EPSILON: f64 = f64::EPSILON * f64::EPSILON;
+
+/// Coordinates need this many times their relative precision between pixels
+/// for rounding errors to stay out of sight
+const PRECISION_MARGIN: f64 = 1024.0;
+
+/// Arithmetic the orbits are computed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Precision {
+    /// `F64`, then `DoubleDouble`, then `Perturbation` as the pixel spacing of the view shrinks;
+    /// formulas perturbation cannot render stay at `DoubleDouble`
+    #[default]
+    #[serde(rename = "auto")]
+    Auto,
+    #[serde(rename = "f64")]
+    F64,
+    /// Double-double, about 106 bits: [`crate::DoubleDouble`]
+    #[serde(rename = "dd")]
+    DoubleDouble,
+    /// Perturbation around an arbitrary precision reference orbit; Mandelbrot only
+    #[serde(rename = "perturbation")]
+    Perturbation,
+}
+
+impl Precision {
+    /// The precision used for the view of `params`, choosing one for `Auto`
+    pub fn resolve(self, params: &RenderParams) -> Precision {
+        match self {
+            Precision::Auto if !below_resolution(params, f64::EPSILON) => Precision::F64,
+            Precision::Auto if !below_resolution(params, DOUBLE_DOUBLE_EPSILON) => Precision::DoubleDouble,
+            Precision::Auto if !perturbation::supports(&params.fractal) => Precision::DoubleDouble,
+            Precision::Auto => Precision::Perturbation,
+            precision => precision,
+        }
+    }
+}
+
+/// Whether numbers with relative precision `epsilon` are too coarse for the pixel spacing of the view
+fn below_resolution(params: &RenderParams, epsilon: f64) -> bool {
+    let spacing = params.viewport.pixel_spacing(params.width, params.height);
+    let scale = params.viewport.center.magnitude().max(1.0);
+    spacing < scale * epsilon * PRECISION_MARGIN
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::complex::Complex;
+    use crate::formula::Fractal;
+    use crate::viewport::Viewport;
+
+    /// A 100 × 100 view of `radius` around `center`, so the pixel spacing is `radius / 50`
+    fn view(center: Complex, radius: f64) -> RenderParams {
+        RenderParams { width: 100, height: 100, viewport: Viewport { center, radius }, ..RenderParams::default() }
+    }
+
+    #[test]
+    fn auto_follows_the_pixel_spacing() {
+        let resolve = |radius| Precision::Auto.resolve(&view(Complex::ZERO, radius));
+        // f64 lasts down to a spacing of 1024 ε ≈ 2.3e-13, double-double to 1024 ε² ≈ 5e-29
+        assert_eq!(resolve(1.0), Precision::F64);
+        assert_eq!(resolve(1.2e-11), Precision::F64);
+        assert_eq!(resolve(1.1e-11), Precision::DoubleDouble);
+        assert_eq!(resolve(2.6e-27), Precision::DoubleDouble);
+        assert_eq!(resolve(2.5e-27), Precision::Perturbation);
+    }
+
+    #[test]
+    fn auto_scales_with_the_center() {
+        let far = Complex::new(-1000.0, 0.0);
+        assert_eq!(Precision::Auto.resolve(&view(far, 1.2e-8)), Precision::F64);
+        assert_eq!(Precision::Auto.resolve(&view(far, 1.1e-8)), Precision::DoubleDouble);
+    }
+
+    #[test]
+    fn auto_keeps_double_double_without_perturbation() {
+        let deep = view(Complex::ZERO, 1e-30);
+        let multibrot = RenderParams { fractal: Fractal::Multibrot { power: 3.0 }, ..deep.clone() };
+        assert_eq!(Precision::Auto.resolve(&multibrot), Precision::DoubleDouble);
+        let julia = RenderParams { julia: Some(Complex::new(-0.8, 0.156)), ..deep };
+        assert_eq!(Precision::Auto.resolve(&julia), Precision::Perturbation);
+    }
+
+    #[test]
+    fn explicit_precisions_are_kept() {
+        for precision in [Precision::F64, Precision::DoubleDouble, Precision::Perturbation] {
+            assert_eq!(precision.resolve(&view(Complex::ZERO, 1.0)), precision);
+            assert_eq!(precision.resolve(&view(Complex::ZERO, 1e-30)), precision);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use std::fmt::{Debug, Display};
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/// Floating point types complex numbers and formulas can be computed in:
+/// `f32` for speed, `f64`, or [`crate::double::DoubleDouble`] for precision
+pub trait Real:
+    Copy
+    + PartialEq
+    + PartialOrd
+    + Debug
+    + Display
+    + Send
+    + Sync
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+{
+    const ZERO: Self;
+    const ONE: Self;
+
+    /// Nearest value to an `f64`
+    fn from_f64(v: f64) -> Self;
+
+    /// Nearest `f64`
+    fn to_f64(self) -> f64;
+
+    fn abs(self) -> Self;
+    /// `self` with the sign of `sign`
+    fn copysign(self, sign: Self) -> Self;
+    fn sqrt(self) -> Self;
+    fn exp(self) -> Self;
+    /// Natural logarithm
+    fn ln(self) -> Self;
+    fn sin(self) -> Self;
+    fn cos(self) -> Self;
+    /// Four quadrant arctangent of `self / x`
+    fn atan2(self, x: Self) -> Self;
+
+    fn sin_cos(self) -> (Self, Self) {
+        (self.sin(), self.cos())
+    }
+
+    fn powf(self, p: Self) -> Self {
+        (self.ln() * p).exp()
+    }
+}
+
+macro_rules! impl_real {
+    ($t:ty) => {
+        impl Real for $t {
+            const ZERO: $t = 0.0;
+            const ONE: $t = 1.0;
+
+            fn from_f64(v: f64) -> $t {
+                v as $t
+            }
+
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+
+            fn abs(self) -> $t {
+                <$t>::abs(self)
+            }
+
+            fn copysign(self, sign: $t) -> $t {
+                <$t>::copysign(self, sign)
+            }
+
+            fn sqrt(self) -> $t {
+                <$t>::sqrt(self)
+            }
+
+            fn exp(self) -> $t {
+                <$t>::exp(self)
+            }
+
+            fn ln(self) -> $t {
+                <$t>::ln(self)
+            }
+
+            fn sin(self) -> $t {
+                <$t>::sin(self)
+            }
+
+            fn cos(self) -> $t {
+                <$t>::cos(self)
+            }
+
+            fn atan2(self, x: $t) -> $t {
+                <$t>::atan2(self, x)
+            }
+
+            fn sin_cos(self) -> ($t, $t) {
+                <$t>::sin_cos(self)
+            }
+
+            fn powf(self, p: $t) -> $t {
+                <$t>::powf(self, p)
+            }
+        }
+    };
+}
+
+impl_real!(f32);
+impl_real!(f64);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::double::DoubleDouble;
+
+    /// `Real` operations of `T` rounded back to `f64`, for comparison with `f64` itself
+    fn apply<T: Real>(x: f64, y: f64) -> [f64; 8] {
+        let (x, y) = (T::from_f64(x), T::from_f64(y));
+        let (sin, cos) = x.sin_cos();
+        [x.sqrt(), x.exp(), x.ln(), sin, cos, x.atan2(y), x.powf(y), x.copysign(-y)].map(Real::to_f64)
+    }
+
+    #[test]
+    fn implementations_agree_with_f64() {
+        for (x, y) in [(0.25, 3.0), (1.5, -0.5), (7.0, 2.25)] {
+            let expected = [x.sqrt(), x.exp(), x.ln(), x.sin(), x.cos(), x.atan2(y), x.powf(y), x.copysign(-y)];
+            for (tolerance, actual) in [(1e-6, apply::<f32>(x, y)), (1e-15, apply::<DoubleDouble>(x, y))] {
+                for (a, e) in actual.iter().zip(expected) {
+                    assert!((a - e).abs() <= tolerance * e.abs().max(1.0), "{a} against {e} for {x}, {y}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn casts_round_trip() {
+        for v in [0.0, -1.0, 0.1, 1e300, -2.5e-300] {
+            assert_eq!(f64::from_f64(v).to_f64(), v);
+            assert_eq!(DoubleDouble::from_f64(v).to_f64(), v);
+        }
+        assert_eq!(f32::from_f64(0.1), 0.1f32);
+        assert_eq!(f32::from_f64(1e300), f32::INFINITY);
+    }
+}
@@ -0,0 +1,159 @@
+use indicatif::ProgressBar;
+
+use crate::antialias::{supersample, Antialias};
+use crate::coloring::{Coloring, Interior};
+use crate::complex::Complex;
+use crate::data::EscapeData;
+use crate::double::DoubleDouble;
+use crate::escape::{iterate_point, Sample};
+use crate::fixed::DecimalComplex;
+use crate::formula::{Fractal, FractalFormula};
+use crate::image::Image;
+use crate::palette::Palette;
+use crate::real::Real;
+use crate::subdivision::compute_region;
+use crate::trap::OrbitTrap;
+use crate::viewport::{map_screen_to_complex, Viewport};
+
+/// Everything needed to render an image
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderParams {
+    /// Image width in pixels
+    pub width: usize,
+    /// Image height in pixels
+    pub height: usize,
+    /// Region of the complex plane to render
+    pub viewport: Viewport,
+    /// Iterated formula
+    pub fractal: Fractal,
+    /// Render the Julia set for this constant instead of the Mandelbrot set
+    pub julia: Option<Complex>,
+    /// Maximum number of iterations for the escape time algorithm
+    pub max_iter: usize,
+    /// Colors for escaping points
+    pub palette: Palette,
+    /// How samples are mapped to colors
+    pub coloring: Coloring,
+    /// How points that never escape are colored
+    pub interior: Interior,
+    /// Supersampling settings
+    pub antialias: Antialias,
+    /// Shape the orbits are compared against
+    pub trap: Option<OrbitTrap>,
+    /// Fill rectangles whose border never escapes instead of iterating their
+    /// inside (Mariani–Silver subdivision), which can miss details thinner than a pixel
+    pub subdivide: bool,
+}
+
+impl Default for RenderParams {
+    fn default() -> RenderParams {
+        RenderParams {
+            width: 1000,
+            height: 1000,
+            viewport: Viewport::default(),
+            fractal: Fractal::default(),
+            julia: None,
+            max_iter: 1000,
+            palette: Palette::default(),
+            coloring: Coloring::default(),
+            interior: Interior::default(),
+            antialias: Antialias::default(),
+            trap: None,
+            subdivide: false,
+        }
+    }
+}
+
+/// Render an image
+pub fn render(params: &RenderParams) -> Image {
+    render_with_progress(params, ProgressBar::hidden())
+}
+
+/// Render an image, reporting one tick per pixel on `pb`
+pub fn render_with_progress(params: &RenderParams, pb: ProgressBar) -> Image {
+    render_formula_with_progress(params, &params.fractal, pb)
+}
+
+/// Render an image with a custom formula; `params.fractal` is ignored
+pub fn render_formula_with_progress<F: FractalFormula + ?Sized>(
+    params: &RenderParams,
+    formula: &F,
+    pb: ProgressBar,
+) -> Image {
+    let data = compute_formula_with_progress(params, formula, pb.clone());
+    let mut img = data.colorize(&params.palette, &params.coloring, params.interior);
+    supersample(params, formula, &mut img, data.histogram(&params.coloring).as_ref(), pb);
+    img
+}
+
+/// Compute the raw escape data of every pixel center, reporting one tick per pixel on `pb`
+pub fn compute_with_progress(params: &RenderParams, pb: ProgressBar) -> EscapeData {
+    compute_formula_with_progress(params, &params.fractal, pb)
+}
+
+/// Compute the raw escape data of every pixel with a custom formula; `params.fractal` is ignored
+pub fn compute_formula_with_progress<F: FractalFormula + ?Sized>(
+    params: &RenderParams,
+    formula: &F,
+    pb: ProgressBar,
+) -> EscapeData {
+    let samples = generate_samples(params, formula, params.viewport, pb);
+    EscapeData {
+        width: params.width,
+        height: params.height,
+        max_iter: params.max_iter,
+        pixel_spacing: params.viewport.pixel_spacing(params.width, params.height),
+        samples,
+    }
+}
+
+/// Render an image in double-double arithmetic around the exact view `center`
+///
+/// Supersampling is not applied; the center of `params.viewport` is ignored in
+/// favor of `center`.
+pub fn render_double_double_with_progress(params: &RenderParams, center: &DecimalComplex, pb: ProgressBar) -> Image {
+    compute_double_double_with_progress(params, center, pb).colorize(&params.palette, &params.coloring, params.interior)
+}
+
+/// Compute the raw escape data of every pixel in double-double arithmetic around the exact view `center`
+pub fn compute_double_double_with_progress(
+    params: &RenderParams,
+    center: &DecimalComplex,
+    pb: ProgressBar,
+) -> EscapeData {
+    let viewport = Viewport { center: center.to_double_double(), radius: params.viewport.radius };
+    let samples = generate_samples::<DoubleDouble, _>(params, &params.fractal, viewport, pb);
+    EscapeData {
+        width: params.width,
+        height: params.height,
+        max_iter: params.max_iter,
+        pixel_spacing: params.viewport.pixel_spacing(params.width, params.height),
+        samples,
+    }
+}
+
+/// Iterate `formula` in the float type of the `viewport` center for every pixel,
+/// or its Julia set when a constant `julia` is given
+fn generate_samples<T: Real, F: FractalFormula<T> + ?Sized>(
+    params: &RenderParams,
+    formula: &F,
+    viewport: Viewport<T>,
+    pb: ProgressBar,
+) -> Vec<Sample> {
+    let &RenderParams { width, height, julia, max_iter, coloring, ref trap, .. } = params;
+    let julia = julia.map(Complex::cast);
+    let distance = coloring.needs_distance();
+
+    pb.set_length((width * height) as u64);
+    let samples = compute_region(
+        params,
+        formula,
+        0..height,
+        |x, y| {
+            let p = map_screen_to_complex(x, y, width, height, &viewport);
+            Some(iterate_point(formula, p, julia, max_iter, distance, trap.as_ref()))
+        },
+        &pb,
+    );
+    samples.expect("pixels are always computed")
+}
@@ -0,0 +1,542 @@
+//! Scene files: every setting of a render in one TOML or JSON document.
+//!
+//! ```toml
+//! interior = "period"
+//!
+//! [view]
+//! center = "-0.745,0.113"
+//! zoom = 50.0
+//!
+//! [fractal]
+//! kind = "mandelbrot"
+//! max_iter = 2000
+//!
+//! # or a formula of your own:
+//! # kind = "custom"
+//! # expression = "z^2 + k*sin(z) + c"
+//! # bailout = "|z| > 4"
+//! # params = { k = "0.5,0" }
+//!
+//! [palette]
+//! name = "fire"
+//! density = 2.0
+//!
+//! [coloring]
+//! mode = "shaded"
+//! width = 1.0
+//!
+//! [trap]
+//! shape = "circle"
+//! center = "0,0"
+//! radius = 0.5
+//!
+//! [output]
+//! file = "fractal.png"
+//! width = 1920
+//! height = 1080
+//! ```
+//!
+//! Every section and field is optional and defaults to the value the command
+//! line uses. Complex numbers, colors and gradients are written as the same
+//! strings the command line accepts.
+//!
+//! Rendered PNG files carry their scene in an iTXt chunk with the keyword
+//! [`SCENE_KEYWORD`], so they can be rendered again later.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::antialias::Antialias;
+use crate::coloring::{Coloring, Interior};
+use crate::complex::Complex;
+use crate::fixed::DecimalComplex;
+use crate::formula::{CustomFormula, Fractal, Multibrot};
+use crate::image::{read_png_text, write_png_with_text, Image};
+use crate::palette::{Gradient, NamedGradient, Palette, Wrap};
+use crate::precision::Precision;
+use crate::render::RenderParams;
+use crate::trap::OrbitTrap;
+use crate::viewport::Viewport;
+
+/// PNG text keyword of the embedded scene
+pub const SCENE_KEYWORD: &str = "fractal-scene";
+
+/// PNG text keyword naming the program that wrote the image
+const SOFTWARE_KEYWORD: &str = "Software";
+
+/// Multibrot exponent when a scene does not give one, as on the command line
+const DEFAULT_POWER: f64 = 3.0;
+
+/// A complete description of a render
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Scene {
+    pub view: ViewSettings,
+    pub fractal: FractalSettings,
+    pub palette: PaletteSettings,
+    pub coloring: Coloring,
+    /// Coloring of the points that never escape
+    pub interior: Interior,
+    pub antialias: Antialias,
+    /// Orbit trap for the trap coloring
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trap: Option<OrbitTrap>,
+    pub output: OutputSettings,
+}
+
+/// Region of the complex plane to render
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ViewSettings {
+    /// Center of the view, kept as decimal strings of any length
+    #[serde(with = "string_form")]
+    pub center: DecimalComplex,
+    /// Zoom factor, 1 shows the whole Mandelbrot set
+    #[serde(deserialize_with = "checked_zoom")]
+    pub zoom: f64,
+}
+
+/// A zoom factor that gives a view
+fn checked_zoom<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    Viewport::check_zoom(f64::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+}
+
+impl Default for ViewSettings {
+    fn default() -> ViewSettings {
+        ViewSettings { center: Complex::new(-0.5, 0.0).into(), zoom: 1.0 }
+    }
+}
+
+/// Iterated formula and iteration settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "FractalSection", into = "FractalSection")]
+pub struct FractalSettings {
+    pub formula: Fractal,
+    /// Render the Julia set for this constant instead of the Mandelbrot set
+    pub julia: Option<Complex>,
+    pub max_iter: usize,
+    /// Arithmetic the orbits are computed in
+    pub precision: Precision,
+    /// Fill rectangles whose border never escapes without iterating them
+    pub subdivide: bool,
+}
+
+impl Default for FractalSettings {
+    fn default() -> FractalSettings {
+        FractalSettings {
+            formula: Fractal::default(),
+            julia: None,
+            max_iter: 1000,
+            precision: Precision::Auto,
+            subdivide: false,
+        }
+    }
+}
+
+/// Names of the built-in formulas in scene files
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum FormulaKind {
+    #[default]
+    Mandelbrot,
+    BurningShip,
+    Tricorn,
+    Multibrot,
+    /// A formula given by `expression`, `bailout` and `params`
+    Custom,
+}
+
+/// `FractalSettings` as written in a scene file, with the formula split into its kind and power
+#[derive(Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FractalSection {
+    kind: FormulaKind,
+    /// Exponent of the Multibrot formula
+    #[serde(skip_serializing_if = "Option::is_none")]
+    power: Option<f64>,
+    /// Step of the custom formula, such as "z^3 + c"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expression: Option<String>,
+    /// Escape condition of the custom formula, "|z| > 2" when absent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bailout: Option<String>,
+    /// Named constants of the custom formula
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    params: BTreeMap<String, Complex>,
+    #[serde(with = "optional_string_form", skip_serializing_if = "Option::is_none")]
+    julia: Option<Complex>,
+    max_iter: usize,
+    precision: Precision,
+    subdivide: bool,
+    /// Older form of `precision = "perturbation"`, still read
+    #[serde(skip_serializing)]
+    deep: bool,
+}
+
+impl Default for FractalSection {
+    fn default() -> FractalSection {
+        FractalSettings::default().into()
+    }
+}
+
+impl TryFrom<FractalSection> for FractalSettings {
+    type Error = String;
+
+    fn try_from(section: FractalSection) -> Result<FractalSettings, String> {
+        let formula = match section.kind {
+            FormulaKind::Mandelbrot => Fractal::Mandelbrot,
+            FormulaKind::BurningShip => Fractal::BurningShip,
+            FormulaKind::Tricorn => Fractal::Tricorn,
+            FormulaKind::Multibrot => {
+                Fractal::Multibrot { power: Multibrot::check_power(section.power.unwrap_or(DEFAULT_POWER))? }
+            }
+            FormulaKind::Custom => {
+                let expression = section.expression.ok_or("a custom formula needs an expression")?;
+                let bailout = section.bailout.as_deref().unwrap_or(CustomFormula::DEFAULT_BAILOUT);
+                let params = section.params.into_iter().collect();
+                Fractal::Custom(CustomFormula::new(&expression, bailout, params)?.into())
+            }
+        };
+        let precision = if section.deep { Precision::Perturbation } else { section.precision };
+        Ok(FractalSettings {
+            formula,
+            julia: section.julia,
+            max_iter: section.max_iter,
+            precision,
+            subdivide: section.subdivide,
+        })
+    }
+}
+
+impl From<FractalSettings> for FractalSection {
+    fn from(settings: FractalSettings) -> FractalSection {
+        let (kind, power, custom) = match settings.formula {
+            Fractal::Mandelbrot => (FormulaKind::Mandelbrot, None, None),
+            Fractal::BurningShip => (FormulaKind::BurningShip, None, None),
+            Fractal::Tricorn => (FormulaKind::Tricorn, None, None),
+            Fractal::Multibrot { power } => (FormulaKind::Multibrot, Some(power), None),
+            Fractal::Custom(custom) => (FormulaKind::Custom, None, Some(custom)),
+        };
+        let expression = custom.as_ref().map(|custom| custom.source().to_string());
+        let bailout = custom
+            .as_ref()
+            .map(|custom| custom.bailout_source())
+            .filter(|&bailout| bailout != CustomFormula::DEFAULT_BAILOUT)
+            .map(str::to_string);
+        let params = custom.iter().flat_map(|custom| custom.params().iter().cloned()).collect();
+        FractalSection {
+            kind,
+            power,
+            expression,
+            bailout,
+            params,
+            julia: settings.julia,
+            max_iter: settings.max_iter,
+            precision: settings.precision,
+            subdivide: settings.subdivide,
+            deep: false,
+        }
+    }
+}
+
+/// Colors for escaping points
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PaletteSettings {
+    /// Built-in gradient, used unless `gradient` is given
+    pub name: NamedGradient,
+    /// Custom gradient as "pos:#rrggbb,pos:#rrggbb,..."
+    #[serde(with = "optional_string_form", skip_serializing_if = "Option::is_none")]
+    pub gradient: Option<Gradient>,
+    pub offset: f64,
+    pub density: f64,
+    pub wrap: Wrap,
+}
+
+impl PaletteSettings {
+    pub fn to_palette(&self) -> Palette {
+        Palette {
+            gradient: self.gradient.clone().unwrap_or_else(|| self.name.gradient()),
+            offset: self.offset,
+            density: self.density,
+            wrap: self.wrap,
+        }
+    }
+}
+
+impl Default for PaletteSettings {
+    fn default() -> PaletteSettings {
+        let palette = Palette::default();
+        PaletteSettings {
+            name: NamedGradient::default(),
+            gradient: None,
+            offset: palette.offset,
+            density: palette.density,
+            wrap: palette.wrap,
+        }
+    }
+}
+
+/// Image file format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// PNG encoded from the whole image in memory
+    #[default]
+    Png,
+    /// PNG computed and encoded in strips of rows, for images too large for memory
+    PngStream,
+}
+
+/// Image size and where it is written
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputSettings {
+    pub file: String,
+    pub width: usize,
+    pub height: usize,
+    pub format: OutputFormat,
+    /// Also save the raw escape data to this file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub save_data: Option<String>,
+}
+
+impl Default for OutputSettings {
+    fn default() -> OutputSettings {
+        OutputSettings {
+            file: "fractal.png".to_string(),
+            width: 1000,
+            height: 1000,
+            format: OutputFormat::default(),
+            save_data: None,
+        }
+    }
+}
+
+impl Scene {
+    /// Render parameters of the scene; deeper zooms than `f64` allows also need the exact `view.center`
+    pub fn to_params(&self) -> RenderParams {
+        RenderParams {
+            width: self.output.width,
+            height: self.output.height,
+            viewport: Viewport::new(self.view.center.to_complex(), self.view.zoom),
+            fractal: self.fractal.formula.clone(),
+            julia: self.fractal.julia,
+            max_iter: self.fractal.max_iter,
+            palette: self.palette.to_palette(),
+            coloring: self.coloring,
+            interior: self.interior,
+            antialias: self.antialias,
+            trap: self.trap.clone(),
+            subdivide: self.fractal.subdivide,
+        }
+    }
+
+    pub fn from_toml(s: &str) -> Result<Scene, String> {
+        toml::from_str(s).map_err(|e| e.to_string())
+    }
+
+    pub fn from_json(s: &str) -> Result<Scene, String> {
+        serde_json::from_str(s).map_err(|e| e.to_string())
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("scenes are representable in TOML")
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("scenes are representable in JSON") + "\n"
+    }
+
+    /// `(keyword, text)` pairs describing the scene in a PNG file, as stored by `write_png_with_scene`
+    pub fn png_text(&self) -> Vec<(&'static str, String)> {
+        vec![
+            (SOFTWARE_KEYWORD, concat!("fractal ", env!("CARGO_PKG_VERSION")).to_string()),
+            (SCENE_KEYWORD, self.to_toml()),
+        ]
+    }
+}
+
+/// Write an image into a PNG file that records the scene it was rendered from
+pub fn write_png_with_scene<P: AsRef<Path>>(filename: P, img: &Image, scene: &Scene) -> std::io::Result<()> {
+    let text = scene.png_text();
+    let text: Vec<(&str, &str)> = text.iter().map(|(k, v)| (*k, v.as_str())).collect();
+    write_png_with_text(filename, img, &text)
+}
+
+/// Read the scene recorded in a PNG file written by this renderer
+pub fn read_png_scene<P: AsRef<Path>>(filename: P) -> std::io::Result<Scene> {
+    let path = filename.as_ref();
+    let invalid = |msg: String| std::io::Error::new(std::io::ErrorKind::InvalidData, msg);
+
+    let (_, text) = read_png_text(path)?
+        .into_iter()
+        .find(|(keyword, _)| keyword == SCENE_KEYWORD)
+        .ok_or_else(|| invalid(format!("{} does not record its render parameters", path.display())))?;
+    Scene::from_toml(&text).map_err(|e| invalid(format!("{}: {e}", path.display())))
+}
+
+/// Read a scene file, as JSON if its extension is `.json` and as TOML otherwise
+pub fn read_scene<P: AsRef<Path>>(filename: P) -> std::io::Result<Scene> {
+    let path = filename.as_ref();
+    let text = std::fs::read_to_string(path)?;
+    let scene = if is_json(path) { Scene::from_json(&text) } else { Scene::from_toml(&text) };
+    scene.map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{}: {e}", path.display())))
+}
+
+/// Write a scene file, as JSON if its extension is `.json` and as TOML otherwise
+pub fn write_scene<P: AsRef<Path>>(filename: P, scene: &Scene) -> std::io::Result<()> {
+    let path = filename.as_ref();
+    let text = if is_json(path) { scene.to_json() } else { scene.to_toml() };
+    std::fs::write(path, text)
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+}
+
+/// Serde adapter for values written as strings with `Display` and `FromStr`
+mod string_form {
+    use super::*;
+
+    pub fn serialize<T: Display, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: FromStr<Err: Display>,
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// `string_form` for optional values
+mod optional_string_form {
+    use super::*;
+
+    pub fn serialize<T: Display, S: Serializer>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => serializer.collect_str(value),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        T: FromStr<Err: Display>,
+        D: Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|s| s.parse().map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+
+    /// A scene that changes every section from its defaults
+    const SCENE: &str = r#"
+interior = "period"
+
+[view]
+center = "-0.7453,0.1127"
+zoom = 250.5
+
+[fractal]
+kind = "custom"
+expression = "z^2 + k*sin(z) + c"
+bailout = "|z| > 4"
+params = { k = "0.5,0.25" }
+julia = "-0.8,0.156"
+max_iter = 750
+precision = "dd"
+subdivide = true
+
+[palette]
+gradient = "0:#000000,0.5:#ff8000,1:#ffffff"
+offset = 0.25
+density = 2.0
+wrap = "mirror"
+
+[coloring]
+mode = "trap"
+
+[antialias]
+samples = 3
+sampling = "jitter"
+adaptive = 16
+seed = 7
+
+[trap]
+shape = "circle"
+center = "0.1,0.2"
+radius = 0.5
+
+[output]
+file = "out.png"
+width = 320
+height = 200
+save_data = "out.fdat"
+"#;
+
+    #[test]
+    fn toml_round_trip() {
+        let scene = Scene::from_toml(SCENE).unwrap();
+        assert_eq!(scene.to_params().max_iter, 750);
+        let reloaded = Scene::from_toml(&scene.to_toml()).unwrap();
+        assert_eq!(reloaded, scene);
+        assert_eq!(reloaded.to_params(), scene.to_params());
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let scene = Scene::from_toml(SCENE).unwrap();
+        let reloaded = Scene::from_json(&scene.to_json()).unwrap();
+        assert_eq!(reloaded, scene);
+        assert_eq!(reloaded.to_params(), scene.to_params());
+    }
+
+    #[test]
+    fn png_keeps_its_scene() {
+        let scene = Scene::from_toml(SCENE).unwrap();
+        let img = Image { width: 3, height: 2, pixels: vec![Color::new(255, 128, 0); 6] };
+        let path = std::env::temp_dir().join(format!("fractal-scene-{}.png", std::process::id()));
+
+        write_png_with_scene(&path, &img, &scene).unwrap();
+        let read = read_png_scene(&path);
+        std::fs::remove_file(&path).unwrap();
+        let read = read.unwrap();
+        assert_eq!(read, scene);
+        assert_eq!(read.to_params(), scene.to_params());
+    }
+
+    #[test]
+    fn rejects_multibrot_powers_without_a_set() {
+        for power in ["1.0", "0.5", "-1.0", "inf"] {
+            let error = Scene::from_toml(&format!("[fractal]\nkind = \"multibrot\"\npower = {power}\n")).unwrap_err();
+            assert!(error.contains("the Multibrot power must be above 1 or below -1"), "{power}: {error}");
+        }
+        let error = Scene::from_json(r#"{ "fractal": { "kind": "multibrot", "power": 0 } }"#).unwrap_err();
+        assert!(error.contains("got 0"), "{error}");
+    }
+
+    #[test]
+    fn rejects_zooms_without_a_view() {
+        for zoom in ["0.0", "-3.0", "inf", "nan"] {
+            let error = Scene::from_toml(&format!("[view]\nzoom = {zoom}\n")).unwrap_err();
+            assert!(error.contains("zoom must be a finite positive number"), "{zoom}: {error}");
+        }
+        let error = Scene::from_json(r#"{ "view": { "zoom": -3 } }"#).unwrap_err();
+        assert!(error.contains("zoom must be a finite positive number, got -3"), "{error}");
+        assert_eq!(Scene::from_toml("[view]\nzoom = 1e-3\n").unwrap().view.zoom, 1e-3);
+    }
+}
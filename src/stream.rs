@@ -0,0 +1,143 @@
+//! Streaming PNG output for images too large to keep in memory.
+//!
+//! The image is computed in strips of rows; each strip is colored, refined by
+//! supersampling and handed to the PNG encoder before the next one is started,
+//! so memory use depends on the image width but not on its height.
+//!
+//! The histogram coloring needs the escape times of the whole image up front;
+//! they are estimated from a first pass over an evenly spaced subset of pixels.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use indicatif::ProgressBar;
+use rayon::prelude::*;
+
+use crate::antialias::{edge_pixels, pixel_color};
+use crate::color::Color;
+use crate::coloring::EscapeHistogram;
+use crate::escape::iterate_point;
+use crate::formula::FractalFormula;
+use crate::image::{png_encoder, Image};
+use crate::render::RenderParams;
+use crate::viewport::map_screen_to_complex;
+
+/// Approximate number of pixels computed at once
+const STRIP_PIXELS: usize = 1 << 20;
+
+/// Render an image straight into a PNG file, strip by strip, with `(keyword, text)` pairs in iTXt chunks
+pub fn write_png_streaming<P: AsRef<Path>>(
+    filename: P,
+    params: &RenderParams,
+    text: &[(&str, &str)],
+    pb: ProgressBar,
+) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(filename)?);
+    encode_png_streaming(&mut writer, params, &params.fractal, text, pb)?;
+    writer.flush()
+}
+
+/// Render an image with a custom formula and encode it as PNG strip by strip; `params.fractal` is ignored
+///
+/// The pixels are identical to those of `render_formula_with_progress` with
+/// `params.subdivide` off: every pixel of a strip is iterated.
+pub fn encode_png_streaming<W: Write, F: FractalFormula + ?Sized>(
+    writer: W,
+    params: &RenderParams,
+    formula: &F,
+    text: &[(&str, &str)],
+    pb: ProgressBar,
+) -> std::io::Result<()> {
+    let &RenderParams { width, height, .. } = params;
+    let encoder = png_encoder(writer, width, height, text)?;
+    let mut png_writer = encoder.write_header().map_err(std::io::Error::other)?;
+    let mut stream = png_writer.stream_writer().map_err(std::io::Error::other)?;
+
+    pb.set_length((width * height) as u64);
+    let histogram = params.coloring.needs_histogram().then(|| estimate_histogram(params, formula));
+    let rows_per_strip = STRIP_PIXELS.div_ceil(width).max(1);
+
+    for start in (0..height).step_by(rows_per_strip) {
+        let end = (start + rows_per_strip).min(height);
+        let strip = render_strip(params, formula, histogram.as_ref(), start, end, &pb);
+
+        let data: Vec<u8> = strip.iter().flat_map(|p| [p.r, p.g, p.b]).collect();
+        stream.write_all(&data)?;
+    }
+
+    stream.finish().map_err(std::io::Error::other)?;
+    pb.finish();
+    Ok(())
+}
+
+/// Escape time distribution of at most `STRIP_PIXELS` pixels spread over the
+/// image; exact for images that small
+fn estimate_histogram<F: FractalFormula + ?Sized>(params: &RenderParams, formula: &F) -> EscapeHistogram {
+    let &RenderParams { width, height, viewport, julia, max_iter, .. } = params;
+    let stride = ((width * height) as f64 / STRIP_PIXELS as f64).sqrt().ceil().max(1.0) as usize;
+    let (columns, rows) = (width.div_ceil(stride), height.div_ceil(stride));
+
+    let samples: Vec<_> = (0..columns * rows)
+        .into_par_iter()
+        .map(|i| {
+            let (x, y) = ((i % columns) * stride, (i / columns) * stride);
+            let p = map_screen_to_complex(x, y, width, height, &viewport);
+            iterate_point(formula, p, julia, max_iter, false, None)
+        })
+        .collect();
+    EscapeHistogram::new(&samples)
+}
+
+/// Colors of rows `start..end`, supersampled like the whole image would be
+fn render_strip<F: FractalFormula + ?Sized>(
+    params: &RenderParams,
+    formula: &F,
+    histogram: Option<&EscapeHistogram>,
+    start: usize,
+    end: usize,
+    pb: &ProgressBar,
+) -> Vec<Color> {
+    let &RenderParams { width, height, viewport, julia, max_iter, ref palette, coloring, interior, antialias: aa, ref trap, .. } = params;
+    let spacing = viewport.pixel_spacing(width, height);
+
+    // Adaptive supersampling compares pixels with their neighbours, so one extra
+    // row is computed on each side of the strip
+    let halo = usize::from(aa.samples > 1 && aa.adaptive.is_some());
+    let (first, last) = (start.saturating_sub(halo), (end + halo).min(height));
+
+    let pixels: Vec<Color> = (first * width..last * width)
+        .into_par_iter()
+        .map(|i| {
+            let p = map_screen_to_complex(i % width, i / width, width, height, &viewport);
+            let sample = iterate_point(formula, p, julia, max_iter, coloring.needs_distance(), trap.as_ref());
+            coloring.color(&sample, palette, spacing, interior, histogram)
+        })
+        .collect();
+    pb.inc(((end - start) * width) as u64);
+
+    let mut img = Image { width, height: last - first, pixels };
+    if aa.samples > 1 {
+        let rows = (start - first) * width..(end - first) * width;
+        let targets: Vec<usize> = match aa.adaptive {
+            None => rows.collect(),
+            Some(threshold) => edge_pixels(&img, threshold).into_iter().filter(|i| rows.contains(i)).collect(),
+        };
+        pb.inc_length(targets.len() as u64);
+
+        let colors: Vec<Color> = targets
+            .par_iter()
+            .map(|&i| {
+                pb.inc(1);
+                pixel_color(params, formula, histogram, i % width, first + i / width)
+            })
+            .collect();
+        for (i, color) in targets.into_iter().zip(colors) {
+            img.pixels[i] = color;
+        }
+    }
+
+    img.pixels.drain((end - first) * width..);
+    img.pixels.drain(..(start - first) * width);
+    img.pixels
+}
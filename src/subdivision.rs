@@ -0,0 +1,195 @@
+//! Mariani–Silver subdivision.
+//!
+//! The bounded part of the Mandelbrot set, like the filled Julia set of a
+//! polynomial, has no holes: when the whole border of a rectangle of pixels
+//! stays bounded, so does its inside. Formulas declare it with
+//! [`FractalFormula::bounded_set_is_full`]. A rectangle is therefore computed by its border first;
+//! a border that never escapes fills the inside without iterating it, any other
+//! border splits the rectangle in two halves that are handled the same way, in
+//! parallel.
+//!
+//! Filled pixels are only known to be bounded, with the period of their border
+//! for the period interior, so only colorings that paint every bounded pixel
+//! alike can use it: the black interior, or the period interior when the whole
+//! border shares its period. The pixels within `MARGIN` of the border must agree
+//! too, since features thinner than a pixel can slip between two border pixels.
+//! Such features can still be missed, which `RenderParams::subdivide` turns off.
+
+use std::ops::Range;
+use std::sync::OnceLock;
+
+use indicatif::ProgressBar;
+use rayon::prelude::*;
+
+use crate::coloring::{Coloring, Interior};
+use crate::complex::Complex;
+use crate::escape::Sample;
+use crate::formula::FractalFormula;
+use crate::real::Real;
+use crate::render::RenderParams;
+
+/// Rectangles narrower or lower than this are computed pixel by pixel
+const MIN_SIZE: usize = 16;
+
+/// Pixels around a rectangle that must share its border's color before it is filled
+const MARGIN: usize = 4;
+
+/// Whether rectangles with a bounded border are filled for `params` and `formula`
+fn fills<T: Real, F: FractalFormula<T> + ?Sized>(params: &RenderParams, formula: &F) -> bool {
+    params.subdivide
+        && formula.bounded_set_is_full()
+        && params.coloring != Coloring::Trap
+        && matches!(params.interior, Interior::Black | Interior::Period)
+}
+
+/// Samples of every pixel in `rows`, row by row, from `compute(x, y)` iterating
+/// `formula`, with one tick per pixel on `pb`; `None` as soon as `compute` gives up
+pub(crate) fn compute_region<T: Real, F: FractalFormula<T> + ?Sized>(
+    params: &RenderParams,
+    formula: &F,
+    rows: Range<usize>,
+    compute: impl Fn(usize, usize) -> Option<Sample> + Sync,
+    pb: &ProgressBar,
+) -> Option<Vec<Sample>> {
+    let width = params.width;
+    if !fills(params, formula) || width == 0 || rows.is_empty() {
+        return (rows.start * width..rows.end * width)
+            .into_par_iter()
+            .map(|i| {
+                let sample = compute(i % width, i / width);
+                pb.inc(1);
+                sample
+            })
+            .collect();
+    }
+
+    let grid = Grid {
+        width,
+        rows: rows.clone(),
+        interior: params.interior,
+        cells: (0..rows.len() * width).map(|_| OnceLock::new()).collect(),
+        compute: &compute,
+        pb,
+    };
+    grid.subdivide(Rect { x: 0..width, y: rows });
+    grid.cells.into_iter().map(|cell| cell.into_inner().flatten()).collect()
+}
+
+/// Pixels `x` × `y`, both ranges exclusive
+#[derive(Debug, Clone)]
+struct Rect {
+    x: Range<usize>,
+    y: Range<usize>,
+}
+
+impl Rect {
+    /// Every pixel on the edge of the rectangle, once each
+    fn border(&self) -> Vec<(usize, usize)> {
+        let (left, right) = (self.x.start, self.x.end - 1);
+        let (top, bottom) = (self.y.start, self.y.end - 1);
+        let mut pixels: Vec<_> = self.x.clone().map(|x| (x, top)).collect();
+        if bottom > top {
+            pixels.extend(self.x.clone().map(|x| (x, bottom)));
+        }
+        pixels.extend((top + 1..bottom).map(|y| (left, y)));
+        if right > left {
+            pixels.extend((top + 1..bottom).map(|y| (right, y)));
+        }
+        pixels
+    }
+
+    /// Every pixel off the edge of the rectangle
+    fn inside(&self) -> impl Iterator<Item = (usize, usize)> + use<> {
+        let x = self.x.start + 1..self.x.end.saturating_sub(1);
+        (self.y.start + 1..self.y.end.saturating_sub(1)).flat_map(move |y| x.clone().map(move |x| (x, y)))
+    }
+}
+
+/// Samples of a band of rows, each computed at most once
+struct Grid<'a, F> {
+    width: usize,
+    rows: Range<usize>,
+    interior: Interior,
+    /// Unset until computed or filled; `None` when the computation gave up
+    cells: Vec<OnceLock<Option<Sample>>>,
+    compute: &'a F,
+    pb: &'a ProgressBar,
+}
+
+impl<F: Fn(usize, usize) -> Option<Sample> + Sync> Grid<'_, F> {
+    fn cell(&self, x: usize, y: usize) -> &OnceLock<Option<Sample>> {
+        &self.cells[(y - self.rows.start) * self.width + x]
+    }
+
+    fn sample(&self, x: usize, y: usize) -> Option<Sample> {
+        *self.cell(x, y).get_or_init(|| {
+            let sample = (self.compute)(x, y);
+            self.pb.inc(1);
+            sample
+        })
+    }
+
+    fn subdivide(&self, rect: Rect) {
+        // Filaments thinner than a pixel slip between border pixels, but mostly
+        // show up in the pixels around them
+        let mut pixels = rect.border();
+        pixels.extend(self.surroundings(&rect));
+        let Some(border) = pixels.into_par_iter().map(|(x, y)| self.sample(x, y)).collect::<Option<Vec<_>>>() else {
+            return;
+        };
+
+        if self.uniform(&border) {
+            let sample = self.filled(&border[0]);
+            let mut filled = 0;
+            for (x, y) in rect.inside() {
+                if self.cell(x, y).set(Some(sample)).is_ok() {
+                    filled += 1;
+                }
+            }
+            self.pb.inc(filled);
+        } else if rect.x.len() < MIN_SIZE || rect.y.len() < MIN_SIZE {
+            rect.inside().collect::<Vec<_>>().into_par_iter().for_each(|(x, y)| {
+                self.sample(x, y);
+            });
+        } else {
+            // Both halves keep the middle line as part of their border
+            let (first, second) = if rect.x.len() >= rect.y.len() {
+                let middle = rect.x.start + rect.x.len() / 2;
+                (Rect { x: rect.x.start..middle + 1, ..rect.clone() }, Rect { x: middle..rect.x.end, ..rect })
+            } else {
+                let middle = rect.y.start + rect.y.len() / 2;
+                (Rect { y: rect.y.start..middle + 1, ..rect.clone() }, Rect { y: middle..rect.y.end, ..rect })
+            };
+            rayon::join(|| self.subdivide(first), || self.subdivide(second));
+        }
+    }
+
+    /// Pixels of the grid outside `rect` but at most `MARGIN` pixels away from it
+    fn surroundings(&self, rect: &Rect) -> Vec<(usize, usize)> {
+        let x = rect.x.start.saturating_sub(MARGIN)..(rect.x.end + MARGIN).min(self.width);
+        let y = rect.y.start.saturating_sub(MARGIN).max(self.rows.start)..(rect.y.end + MARGIN).min(self.rows.end);
+        y.flat_map(|y| x.clone().map(move |x| (x, y)))
+            .filter(|(x, y)| !rect.x.contains(x) || !rect.y.contains(y))
+            .collect()
+    }
+
+    /// Sample of the pixels inside a uniform border: bounded, with the period the
+    /// interior shows, and no other details
+    fn filled(&self, border: &Sample) -> Sample {
+        Sample {
+            escape_time: None,
+            z: Complex::ZERO,
+            period: border.period.filter(|_| self.interior == Interior::Period),
+            distance: None,
+            multiplier: None,
+            trap: None,
+        }
+    }
+
+    /// Whether a border colors the inside of its rectangle alike
+    fn uniform(&self, border: &[Sample]) -> bool {
+        border.iter().all(|sample| {
+            sample.escape_time.is_none() && (self.interior != Interior::Period || sample.period == border[0].period)
+        })
+    }
+}
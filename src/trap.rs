@@ -0,0 +1,168 @@
+//! Orbit traps: the orbit is colored by how close it comes to a shape.
+//!
+//! Every orbit value z_1, z_2, ... until escape is compared to the trap; the
+//! geometric traps keep the smallest distance, the image trap keeps the color
+//! of the first opaque pixel of a bitmap laid over the complex plane.
+//! https://en.wikipedia.org/wiki/Orbit_trap
+
+use std::path::Path;
+use std::sync::Arc;
+
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+
+use crate::color::Color;
+use crate::complex::Complex;
+
+/// Shape the orbit is compared against
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "shape", rename_all = "kebab-case")]
+pub enum OrbitTrap {
+    /// A single point
+    Point { center: Complex },
+    /// The line through `point` at `angle` degrees from the real axis
+    Line { point: Complex, angle: f64 },
+    /// The horizontal and vertical lines through `center`
+    Cross { center: Complex },
+    /// The circle of `radius` around `center`
+    Circle { center: Complex, radius: f64 },
+    /// A bitmap covering a rectangle of the plane
+    Image(ImageTrap),
+}
+
+/// An image laid over the complex plane, `size` wide and centered on `center`
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "ImageTrapSpec", into = "ImageTrapSpec")]
+pub struct ImageTrap {
+    /// File the bitmap was loaded from
+    pub path: String,
+    pub center: Complex,
+    /// Width of the image in the complex plane; the height follows its aspect ratio
+    pub size: f64,
+    bitmap: Arc<RgbaImage>,
+}
+
+/// Pixels with at least this alpha value catch the orbit
+const OPAQUE_ALPHA: u8 = 128;
+
+impl ImageTrap {
+    /// Load the bitmap from an image file
+    pub fn open<P: AsRef<Path>>(path: P, center: Complex, size: f64) -> Result<ImageTrap, String> {
+        let path = path.as_ref();
+        let bitmap = image::open(path)
+            .map_err(|e| format!("cannot read trap image {}: {e}", path.display()))?
+            .to_rgba8();
+        if bitmap.width() == 0 || bitmap.height() == 0 {
+            return Err(format!("trap image {} is empty", path.display()));
+        }
+        Ok(ImageTrap { path: path.display().to_string(), center, size, bitmap: Arc::new(bitmap) })
+    }
+
+    /// Color of the opaque pixel under `z`, if any
+    fn color_at(&self, z: Complex) -> Option<Color> {
+        let (w, h) = self.bitmap.dimensions();
+        let height = self.size * h as f64 / w as f64;
+
+        let u = (z.re - self.center.re) / self.size + 0.5;
+        let v = (self.center.im - z.im) / height + 0.5;
+        if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+            return None;
+        }
+
+        let pixel = self.bitmap.get_pixel((u * w as f64) as u32, (v * h as f64) as u32).0;
+        (pixel[3] >= OPAQUE_ALPHA).then(|| Color::new(pixel[0], pixel[1], pixel[2]))
+    }
+}
+
+impl std::fmt::Debug for ImageTrap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ImageTrap")
+            .field("path", &self.path)
+            .field("center", &self.center)
+            .field("size", &self.size)
+            .field("bitmap", &self.bitmap.dimensions())
+            .finish()
+    }
+}
+
+impl PartialEq for ImageTrap {
+    fn eq(&self, other: &ImageTrap) -> bool {
+        self.path == other.path
+            && self.center == other.center
+            && self.size == other.size
+            && (Arc::ptr_eq(&self.bitmap, &other.bitmap) || self.bitmap.as_raw() == other.bitmap.as_raw())
+    }
+}
+
+/// `ImageTrap` as written in a scene file: the bitmap is loaded from `path`
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ImageTrapSpec {
+    path: String,
+    center: Complex,
+    size: f64,
+}
+
+impl TryFrom<ImageTrapSpec> for ImageTrap {
+    type Error = String;
+
+    fn try_from(spec: ImageTrapSpec) -> Result<ImageTrap, String> {
+        ImageTrap::open(&spec.path, spec.center, spec.size)
+    }
+}
+
+impl From<ImageTrap> for ImageTrapSpec {
+    fn from(trap: ImageTrap) -> ImageTrapSpec {
+        ImageTrapSpec { path: trap.path, center: trap.center, size: trap.size }
+    }
+}
+
+/// What an orbit left in the trap
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrapHit {
+    /// Smallest distance to a geometric trap, in the complex plane
+    Distance(f64),
+    /// Color of the image trap pixel the orbit first landed on
+    Color(Color),
+}
+
+/// Follows one orbit through the trap
+pub(crate) struct TrapTracker<'a> {
+    trap: &'a OrbitTrap,
+    hit: Option<TrapHit>,
+}
+
+impl<'a> TrapTracker<'a> {
+    pub(crate) fn new(trap: &'a OrbitTrap) -> TrapTracker<'a> {
+        TrapTracker { trap, hit: None }
+    }
+
+    /// Compare the next orbit value to the trap
+    pub(crate) fn visit(&mut self, z: Complex) {
+        let distance = match self.trap {
+            OrbitTrap::Point { center } => (z - *center).magnitude(),
+            OrbitTrap::Line { point, angle } => {
+                // Component of z - point across the line direction
+                let (sin, cos) = angle.to_radians().sin_cos();
+                ((z.im - point.im) * cos - (z.re - point.re) * sin).abs()
+            }
+            OrbitTrap::Cross { center } => (z.re - center.re).abs().min((z.im - center.im).abs()),
+            OrbitTrap::Circle { center, radius } => ((z - *center).magnitude() - radius).abs(),
+            OrbitTrap::Image(image) => {
+                if self.hit.is_none() {
+                    self.hit = image.color_at(z).map(TrapHit::Color);
+                }
+                return;
+            }
+        };
+
+        match self.hit {
+            Some(TrapHit::Distance(best)) if best <= distance => {}
+            _ => self.hit = Some(TrapHit::Distance(distance)),
+        }
+    }
+
+    pub(crate) fn finish(self) -> Option<TrapHit> {
+        self.hit
+    }
+}
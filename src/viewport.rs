@@ -0,0 +1,85 @@
+use crate::complex::Complex;
+use crate::real::Real;
+
+/// Region of the complex plane to render, with its center in the float type `T`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport<T = f64> {
+    pub center: Complex<T>,
+    /// Half the extent of the shorter image side in the complex plane
+    pub radius: f64,
+}
+
+impl Viewport {
+    /// Radius of the view at zoom 1, enough to fit the whole Mandelbrot set
+    pub const BASE_RADIUS: f64 = 1.5;
+
+    /// `zoom` if it gives a view: finite and positive
+    pub fn check_zoom(zoom: f64) -> Result<f64, String> {
+        if !(zoom > 0.0 && zoom.is_finite()) {
+            return Err(format!("zoom must be a finite positive number, got {zoom}"));
+        }
+        Ok(zoom)
+    }
+}
+
+impl<T: Real> Viewport<T> {
+    pub fn new(center: Complex<T>, zoom: f64) -> Viewport<T> {
+        Viewport { center, radius: Viewport::BASE_RADIUS / zoom }
+    }
+
+    /// Distance between neighbouring pixel centers in the complex plane
+    pub fn pixel_spacing(&self, width: usize, height: usize) -> f64 {
+        2.0 * self.radius / width.min(height) as f64
+    }
+}
+
+impl Default for Viewport {
+    fn default() -> Viewport {
+        Viewport::new(Complex::new(-0.5, 0.0), 1.0)
+    }
+}
+
+/// Map screen plane coordinates to complex plane coordinates, in the float type of the viewport center
+pub fn map_screen_to_complex<T: Real>(
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    viewport: &Viewport<T>,
+) -> Complex<T> {
+    map_subpixel_to_complex(x as f64 + 0.5, y as f64 + 0.5, width, height, viewport)
+}
+
+/// Map continuous screen coordinates, where pixel (x, y) covers [x, x + 1) x [y, y + 1),
+/// to complex plane coordinates
+pub fn map_subpixel_to_complex<T: Real>(
+    x: f64,
+    y: f64,
+    width: usize,
+    height: usize,
+    viewport: &Viewport<T>,
+) -> Complex<T> {
+    // Same scale on both axes so non-square images are not stretched
+    let scale = viewport.pixel_spacing(width, height);
+
+    // The offset from the center is small enough for `f64`
+    let re = viewport.center.re + T::from_f64((x - width as f64 / 2.0) * scale);
+    let im = viewport.center.im - T::from_f64((y - height as f64 / 2.0) * scale);
+
+    Complex { re, im }
+}
+
+/// Map a complex plane point to the pixel containing it, `None` outside the image
+pub fn map_complex_to_screen(p: Complex, width: usize, height: usize, viewport: &Viewport) -> Option<(usize, usize)> {
+    let scale = viewport.pixel_spacing(width, height);
+
+    let x = (p.re - viewport.center.re) / scale + width as f64 / 2.0;
+    let y = (viewport.center.im - p.im) / scale + height as f64 / 2.0;
+
+    // Also rejects NaN
+    if x >= 0.0 && y >= 0.0 && x < width as f64 && y < height as f64 {
+        Some((x as usize, y as usize))
+    } else {
+        None
+    }
+}
@@ -0,0 +1,96 @@
+//! Mariani–Silver subdivision must not change the image of standard views.
+
+use std::sync::Arc;
+
+use fractal::{compute_with_progress, render, Complex, CustomFormula, Fractal, Interior, RenderParams, Viewport};
+use indicatif::ProgressBar;
+
+/// Render `params` with and without subdivision and compare every pixel
+fn assert_same_image(name: &str, params: RenderParams) {
+    let subdivided = render(&RenderParams { subdivide: true, ..params.clone() });
+    let exact = render(&RenderParams { subdivide: false, ..params });
+    let differing = subdivided.pixels.iter().zip(&exact.pixels).filter(|(a, b)| a != b).count();
+    assert_eq!(differing, 0, "{name}: {differing} pixels differ");
+}
+
+fn view(re: f64, im: f64, zoom: f64) -> RenderParams {
+    let viewport = Viewport::new(Complex::new(re, im), zoom);
+    RenderParams { width: 240, height: 160, viewport, ..RenderParams::default() }
+}
+
+#[test]
+fn whole_mandelbrot_set() {
+    assert_same_image("whole set", view(-0.5, 0.0, 1.0));
+}
+
+#[test]
+fn period_three_bulb() {
+    assert_same_image("period 3 bulb", view(-0.1226, 0.7449, 8.0));
+}
+
+#[test]
+fn period_interior() {
+    assert_same_image("period interior", RenderParams { interior: Interior::Period, ..view(-0.5, 0.0, 1.0) });
+}
+
+#[test]
+fn mini_mandelbrot() {
+    assert_same_image("mini mandelbrot", RenderParams { max_iter: 2000, ..view(-1.75, 0.0, 30.0) });
+}
+
+#[test]
+fn julia_set() {
+    assert_same_image("julia set", RenderParams { julia: Some(Complex::new(-0.8, 0.156)), ..view(0.0, 0.0, 1.0) });
+}
+
+#[test]
+fn multibrot() {
+    assert_same_image("multibrot", RenderParams { fractal: Fractal::Multibrot { power: 3.0 }, ..view(0.0, 0.0, 1.0) });
+}
+
+#[test]
+fn filled_samples_carry_no_details() {
+    let params = RenderParams { interior: Interior::Period, ..view(-0.5, 0.0, 1.0) };
+    let subdivided = compute_with_progress(&RenderParams { subdivide: true, ..params.clone() }, ProgressBar::hidden());
+    let exact = compute_with_progress(&RenderParams { subdivide: false, ..params }, ProgressBar::hidden());
+    let filled: Vec<_> = subdivided.samples.iter().zip(&exact.samples).filter(|(a, b)| a != b).collect();
+    assert!(!filled.is_empty());
+    for (sample, exact) in filled {
+        assert_eq!((sample.escape_time, sample.period), (None, exact.period));
+        assert_eq!((sample.z, sample.distance, sample.multiplier), (Complex::ZERO, None, None));
+    }
+}
+
+#[test]
+fn only_formulas_without_holes_are_filled() {
+    let custom = CustomFormula::new("z^2 + c", CustomFormula::DEFAULT_BAILOUT, Vec::new()).unwrap();
+    for fractal in [Fractal::BurningShip, Fractal::Multibrot { power: 2.5 }, Fractal::Custom(Arc::new(custom))] {
+        let params = RenderParams { fractal, max_iter: 100, ..view(-0.5, 0.0, 1.0) };
+        let compute =
+            |subdivide| compute_with_progress(&RenderParams { subdivide, ..params.clone() }, ProgressBar::hidden());
+        let (subdivided, exact) = (compute(true), compute(false));
+        let differing = subdivided.samples.iter().zip(&exact.samples).filter(|(a, b)| a != b).count();
+        assert_eq!(differing, 0, "{:?}", params.fractal);
+    }
+}
+
+/// A 1500 × 1400 view, fine enough for filaments thinner than a pixel to cross rectangle borders
+fn poster(re: f64, im: f64, zoom: f64) -> RenderParams {
+    RenderParams { width: 1500, height: 1400, ..view(re, im, zoom) }
+}
+
+#[test]
+fn seahorse_valley_poster() {
+    assert_same_image("seahorse valley", poster(-0.745, 0.113, 50.0));
+}
+
+#[test]
+fn deep_filaments() {
+    assert_same_image("deep filaments", RenderParams { max_iter: 5000, ..view(-1.25066, 0.02012, 1000.0) });
+    assert_same_image("deep filaments poster", RenderParams { max_iter: 5000, ..poster(-1.25066, 0.02012, 1000.0) });
+}
+
+#[test]
+fn origin_poster() {
+    assert_same_image("origin", poster(0.0, 0.0, 1.0));
+}